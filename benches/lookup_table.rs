@@ -0,0 +1,34 @@
+//! 候选表序列化: 完整发送 vs 只发送当前页
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use librush::ibus::{Candidate, IBusSerializable, LookupTable, VirtualLookupTable};
+
+const N: usize = 10000;
+
+fn candidates() -> Vec<String> {
+    (0..N).map(|i| format!("候选{}", i)).collect()
+}
+
+fn serialize(c: &mut Criterion) {
+    let mut full = LookupTable::new(candidates(), 9, true, false).unwrap();
+    full.set_cursor_pos(N as i64 / 2);
+    c.bench_function("LookupTable 10k", |b| {
+        b.iter(|| black_box(&full).serialize())
+    });
+
+    let v: Vec<Candidate> = candidates().into_iter().map(Candidate::from).collect();
+    let mut page = VirtualLookupTable::new(v, 9, true, false).unwrap();
+    page.set_cursor_pos(N as i64 / 2);
+    c.bench_function("VirtualLookupTable 10k", |b| {
+        b.iter(|| black_box(&page).lookup_table().serialize())
+    });
+
+    page.window = 2;
+    c.bench_function("VirtualLookupTable 10k (window 2)", |b| {
+        b.iter(|| black_box(&page).lookup_table().serialize())
+    });
+}
+
+criterion_group!(benches, serialize);
+criterion_main!(benches);
@@ -10,23 +10,36 @@ pm_init!();
 
 use librush::pmim;
 
-fn main() -> Result<(), ExitCode> {
+fn main() -> ExitCode {
     init_env_logger();
 
     let mut flatpak = false;
+    // 默认: 由 ibus-daemon 启动 (`--ibus`)
+    let mut standalone = false;
     if let Some(a) = cli_arg(print_version) {
-        if a.len() > 0 {
-            match a[0].as_str() {
+        for i in a.iter() {
+            match i.as_str() {
                 "--flatpak" => {
                     flatpak = true;
                 }
+                "--ibus" => {
+                    standalone = false;
+                }
+                // 注册组件, 不需要安装 xml 文件
+                "--standalone" => {
+                    standalone = true;
+                }
+                // 输出组件信息 (`/usr/share/ibus/component/pmim_ibrus.xml`)
+                "--xml" => {
+                    print!("{}", pmim::component().to_xml());
+                    return ExitCode::SUCCESS;
+                }
                 _ => {}
             }
         }
 
-        pmim::main(flatpak).unwrap();
-        Ok(())
+        pmim::main(flatpak, standalone).unwrap()
     } else {
-        Ok(())
+        ExitCode::SUCCESS
     }
 }
@@ -0,0 +1,749 @@
+//! 同步 (阻塞) 的 engine: 在单独的线程运行
+//!
+//! [`SyncIBusEngine`] 使用普通的 `fn`, 通过 [`SyncEngineHandle`] 更新界面.
+//! [`BlockingEngine`] 把它转换为 [`IBusEngine`].
+use std::io;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::thread;
+
+use async_channel::Sender;
+use pm_bin::log::error;
+use xkeysym::{KeyCode, Keysym};
+use zbus::{ObjectServer, fdo, object_server::SignalEmitter};
+
+use super::{
+    EngineContext, IBusCapabilite, IBusEngine, IBusEngineBackend, IBusExtensionEvent,
+    IBusExtensionKeys, IBusInputHints, IBusInputPurpose, IBusModifierState, IBusPreeditFocusMode,
+    IBusPropList, IBusPropState, IBusProperty, IBusStroke, IBusText, LookupTable,
+};
+
+/// Synchronous version of [`IBusEngine`]
+///
+/// The callbacks are plain functions, called one after another on a dedicated thread, so they
+/// can block (for example on a table lookup). Use the [`SyncEngineHandle`] to display text to
+/// the user, and [`Self::blocking`] to get an [`IBusEngine`].
+///
+/// ```
+/// use librush::ibus::IBusModifierState;
+/// use librush::ibus::blocking::{BlockingEngine, SyncEngineHandle, SyncIBusEngine};
+/// use librush::ibus::xkeysym::{KeyCode, Keysym};
+///
+/// struct Table;
+///
+/// impl SyncIBusEngine for Table {
+///     fn process_key_event(
+///         &mut self,
+///         h: &mut SyncEngineHandle,
+///         keyval: Keysym,
+///         _keycode: KeyCode,
+///         state: IBusModifierState,
+///     ) -> bool {
+///         if state.is_keyup() || keyval != Keysym::a {
+///             return false;
+///         }
+///         h.commit_text("啊");
+///         true
+///     }
+/// }
+///
+/// let _e: BlockingEngine<Table> = Table.blocking()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub trait SyncIBusEngine: Send + 'static {
+    /// Runs this engine on its own thread, see [`BlockingEngine::new`]
+    fn blocking(self) -> io::Result<BlockingEngine<Self>>
+    where
+        Self: Sized,
+    {
+        BlockingEngine::new(self)
+    }
+
+    /// See [`IBusEngine::set_context`]
+    ///
+    /// The methods of [`EngineContext`] are `async`: use them from another thread, for example
+    /// with [`crate::ibus::block_on`].
+    fn set_context(&mut self, _ctx: EngineContext) {}
+
+    /// See [`IBusEngine::process_key_event`]
+    fn process_key_event(
+        &mut self,
+        _h: &mut SyncEngineHandle,
+        _keyval: Keysym,
+        _keycode: KeyCode,
+        _state: IBusModifierState,
+    ) -> bool {
+        false
+    }
+
+    /// See [`IBusEngine::process_hand_writing_event`]
+    fn process_hand_writing_event(&mut self, _h: &mut SyncEngineHandle, _stroke: IBusStroke) {}
+
+    /// See [`IBusEngine::cancel_hand_writing`]
+    fn cancel_hand_writing(&mut self, _h: &mut SyncEngineHandle, _n_strokes: u32) {}
+
+    /// See [`IBusEngine::set_cursor_location`]
+    fn set_cursor_location(
+        &mut self,
+        _handle: &mut SyncEngineHandle,
+        _x: i32,
+        _y: i32,
+        _w: i32,
+        _h: i32,
+    ) {
+    }
+
+    /// See [`IBusEngine::set_capabilities`]
+    fn set_capabilities(&mut self, _h: &mut SyncEngineHandle, _caps: IBusCapabilite) {}
+
+    /// See [`IBusEngine::focus_in`]
+    fn focus_in(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::focus_out`]
+    fn focus_out(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::focus_in_id`], the default implementation calls [`Self::focus_in`]
+    fn focus_in_id(&mut self, h: &mut SyncEngineHandle, _object_path: String, _client: String) {
+        self.focus_in(h)
+    }
+
+    /// See [`IBusEngine::focus_out_id`], the default implementation calls [`Self::focus_out`]
+    fn focus_out_id(&mut self, h: &mut SyncEngineHandle, _object_path: String) {
+        self.focus_out(h)
+    }
+
+    /// See [`IBusEngine::reset`]
+    fn reset(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::enable`]
+    fn enable(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::disable`]
+    fn disable(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::destroy`]
+    ///
+    /// The thread of the engine stops after this.
+    fn destroy(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::candidate_clicked`]
+    fn candidate_clicked(
+        &mut self,
+        _h: &mut SyncEngineHandle,
+        _index: u32,
+        _button: u32,
+        _state: u32,
+    ) {
+    }
+
+    /// See [`IBusEngine::page_up`]
+    fn page_up(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::page_down`]
+    fn page_down(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::cursor_up`]
+    fn cursor_up(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::cursor_down`]
+    fn cursor_down(&mut self, _h: &mut SyncEngineHandle) {}
+
+    /// See [`IBusEngine::set_surrounding_text`]
+    fn set_surrounding_text(
+        &mut self,
+        _h: &mut SyncEngineHandle,
+        _text: IBusText,
+        _cursor_pos: u32,
+        _anchor_pos: u32,
+    ) {
+    }
+
+    /// See [`IBusEngine::set_content_type`]
+    fn set_content_type(
+        &mut self,
+        _h: &mut SyncEngineHandle,
+        _purpose: IBusInputPurpose,
+        _hints: IBusInputHints,
+    ) {
+    }
+
+    /// See [`IBusEngine::property_activate`]
+    fn property_activate(
+        &mut self,
+        _h: &mut SyncEngineHandle,
+        _name: String,
+        _state: IBusPropState,
+    ) {
+    }
+
+    /// See [`IBusEngine::property_show`]
+    fn property_show(&mut self, _h: &mut SyncEngineHandle, _name: String) {}
+
+    /// See [`IBusEngine::property_hide`]
+    fn property_hide(&mut self, _h: &mut SyncEngineHandle, _name: String) {}
+
+    /// See [`IBusEngine::panel_extension_received`]
+    fn panel_extension_received(&mut self, _h: &mut SyncEngineHandle, _event: IBusExtensionEvent) {}
+
+    /// See [`IBusEngine::panel_extension_register_keys`]
+    fn panel_extension_register_keys(
+        &mut self,
+        _h: &mut SyncEngineHandle,
+        _keys: IBusExtensionKeys,
+    ) {
+    }
+}
+
+/// 等待发送的信号
+#[derive(Debug, Clone)]
+enum 信号 {
+    CommitText(IBusText),
+    UpdateLookupTable(LookupTable, bool),
+    UpdatePreeditText(IBusText, u32, bool, IBusPreeditFocusMode),
+    UpdateAuxiliaryText(IBusText, bool),
+    RegisterProperties(IBusPropList),
+    UpdateProperty(Box<IBusProperty>),
+    ForwardKeyEvent(Keysym, KeyCode, IBusModifierState),
+    PanelExtension(IBusExtensionEvent),
+    DeleteSurroundingText(i32, u32),
+    RequireSurroundingText,
+}
+
+/// Updates the UI from a [`SyncIBusEngine`] callback
+///
+/// Same methods as [`IBusEngineBackend`], without `async`: the signals are collected, and sent
+/// in order when the callback returns (before the reply to ibus-daemon).
+#[derive(Debug, Default)]
+pub struct SyncEngineHandle {
+    s: Vec<信号>,
+}
+
+impl SyncEngineHandle {
+    /// See [`IBusEngineBackend::commit_text`]
+    pub fn commit_text(&mut self, text: impl Into<IBusText>) {
+        self.s.push(信号::CommitText(text.into()));
+    }
+
+    /// See [`IBusEngineBackend::update_lookup_table`]
+    pub fn update_lookup_table(&mut self, table: &LookupTable, visible: bool) {
+        self.s.push(信号::UpdateLookupTable(table.clone(), visible));
+    }
+
+    /// See [`IBusEngineBackend::update_preedit_text`]
+    pub fn update_preedit_text(
+        &mut self,
+        text: impl Into<IBusText>,
+        cursor_pos: u32,
+        visible: bool,
+        mode: IBusPreeditFocusMode,
+    ) {
+        self.s.push(信号::UpdatePreeditText(
+            text.into(),
+            cursor_pos,
+            visible,
+            mode,
+        ));
+    }
+
+    /// See [`IBusEngineBackend::update_auxiliary_text`]
+    pub fn update_auxiliary_text(&mut self, text: impl Into<IBusText>, visible: bool) {
+        self.s.push(信号::UpdateAuxiliaryText(text.into(), visible));
+    }
+
+    /// See [`IBusEngineBackend::register_properties`]
+    pub fn register_properties(&mut self, props: &IBusPropList) {
+        self.s.push(信号::RegisterProperties(props.clone()));
+    }
+
+    /// See [`IBusEngineBackend::update_property`]
+    pub fn update_property(&mut self, prop: &IBusProperty) {
+        self.s.push(信号::UpdateProperty(Box::new(prop.clone())));
+    }
+
+    /// See [`IBusEngineBackend::forward_key_event`]
+    pub fn forward_key_event(
+        &mut self,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) {
+        self.s.push(信号::ForwardKeyEvent(keyval, keycode, state));
+    }
+
+    /// See [`IBusEngineBackend::forward_key_press_release`]
+    pub fn forward_key_press_release(
+        &mut self,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) {
+        self.forward_key_event(keyval, keycode, state.with_release(false));
+        self.forward_key_event(keyval, keycode, state.with_release(true));
+    }
+
+    /// See [`IBusEngineBackend::panel_extension`]
+    pub fn panel_extension(&mut self, event: &IBusExtensionEvent) {
+        self.s.push(信号::PanelExtension(event.clone()));
+    }
+
+    /// See [`IBusEngineBackend::delete_surrounding_text`]
+    pub fn delete_surrounding_text(&mut self, offset: i32, n_chars: u32) {
+        self.s.push(信号::DeleteSurroundingText(offset, n_chars));
+    }
+
+    /// See [`IBusEngineBackend::require_surrounding_text`]
+    pub fn require_surrounding_text(&mut self) {
+        self.s.push(信号::RequireSurroundingText);
+    }
+}
+
+type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Runs a [`SyncIBusEngine`] on a dedicated thread, as an [`IBusEngine`]
+///
+/// Every callback is sent to the thread, and awaited without blocking the async executor. The
+/// thread stops when the engine is removed from the bus (after [`SyncIBusEngine::destroy`]).
+///
+/// If a callback panics, the panic is logged and ibus-daemon gets an error for this call; the
+/// thread keeps running the next callbacks.
+#[derive(Debug)]
+pub struct BlockingEngine<T: SyncIBusEngine> {
+    j: Sender<Job<T>>,
+}
+
+impl<T: SyncIBusEngine> BlockingEngine<T> {
+    /// Starts the thread of the engine
+    ///
+    /// Fails if the thread can not be created.
+    pub fn new(e: T) -> io::Result<Self> {
+        let (j, r) = async_channel::unbounded::<Job<T>>();
+
+        thread::Builder::new()
+            .name("librush-engine".to_string())
+            .spawn(move || {
+                let mut e = e;
+                while let Ok(f) = r.recv_blocking() {
+                    // 回调 panic 时, 继续处理下一个回调
+                    if let Err(p) = catch_unwind(AssertUnwindSafe(|| f(&mut e))) {
+                        error!("engine 回调 panic: {}", panic信息(&*p));
+                    }
+                }
+            })?;
+
+        Ok(Self { j })
+    }
+
+    /// 在 engine 线程调用 `f`, 然后发送收集的信号
+    async fn call<R: Send + 'static>(
+        &self,
+        se: &SignalEmitter<'_>,
+        f: impl FnOnce(&mut T, &mut SyncEngineHandle) -> R + Send + 'static,
+    ) -> fdo::Result<R> {
+        let (tx, rx) = async_channel::bounded(1);
+        let job: Job<T> = Box::new(move |e| {
+            let mut h = SyncEngineHandle::default();
+            let r = f(e, &mut h);
+            // 忽略错误
+            let _ = tx.send_blocking((r, h));
+        });
+        self.j
+            .send(job)
+            .await
+            .map_err(|_| fdo::Error::Failed("engine thread stopped".to_string()))?;
+        // 回调 panic 时 `tx` 被丢弃
+        let (r, h) = rx
+            .recv()
+            .await
+            .map_err(|_| fdo::Error::Failed("engine callback panicked".to_string()))?;
+
+        for s in h.s {
+            发送::<Self>(se, s).await?;
+        }
+        Ok(r)
+    }
+}
+
+/// `panic!` 的消息
+fn panic信息(p: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(s) = p.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = p.downcast_ref::<String>() {
+        s
+    } else {
+        "?"
+    }
+}
+
+async fn 发送<T: IBusEngineBackend>(se: &SignalEmitter<'_>, s: 信号) -> zbus::Result<()> {
+    match s {
+        信号::CommitText(text) => T::commit_text(se, text).await,
+        信号::UpdateLookupTable(table, visible) => {
+            T::update_lookup_table(se, &table, visible).await
+        }
+        信号::UpdatePreeditText(text, cursor_pos, visible, mode) => {
+            T::update_preedit_text(se, text, cursor_pos, visible, mode).await
+        }
+        信号::UpdateAuxiliaryText(text, visible) => {
+            T::update_auxiliary_text(se, text, visible).await
+        }
+        信号::RegisterProperties(props) => T::register_properties(se, &props).await,
+        信号::UpdateProperty(prop) => T::update_property(se, &prop).await,
+        信号::ForwardKeyEvent(keyval, keycode, state) => {
+            T::forward_key_event(se, keyval, keycode, state).await
+        }
+        信号::PanelExtension(event) => T::panel_extension(se, &event).await,
+        信号::DeleteSurroundingText(offset, n_chars) => {
+            T::delete_surrounding_text(se, offset, n_chars).await
+        }
+        信号::RequireSurroundingText => T::require_surrounding_text(se).await,
+    }
+}
+
+impl<T: SyncIBusEngine> IBusEngine for BlockingEngine<T> {
+    fn set_context(&mut self, ctx: EngineContext) {
+        // 通道没有容量限制, 忽略错误
+        let _ = self.j.try_send(Box::new(move |e| e.set_context(ctx)));
+    }
+
+    async fn process_key_event(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> fdo::Result<bool> {
+        self.call(&se, move |e, h| {
+            e.process_key_event(h, keyval, keycode, state)
+        })
+        .await
+    }
+
+    async fn process_hand_writing_event(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        stroke: IBusStroke,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.process_hand_writing_event(h, stroke))
+            .await
+    }
+
+    async fn cancel_hand_writing(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        n_strokes: u32,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.cancel_hand_writing(h, n_strokes))
+            .await
+    }
+
+    async fn set_cursor_location(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, handle| {
+            e.set_cursor_location(handle, x, y, w, h)
+        })
+        .await
+    }
+
+    async fn set_capabilities(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        caps: IBusCapabilite,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.set_capabilities(h, caps))
+            .await
+    }
+
+    async fn focus_in(&mut self, se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.focus_in(h)).await
+    }
+
+    async fn focus_out(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.focus_out(h)).await
+    }
+
+    async fn focus_in_id(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        object_path: String,
+        client: String,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.focus_in_id(h, object_path, client))
+            .await
+    }
+
+    async fn focus_out_id(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        object_path: String,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.focus_out_id(h, object_path))
+            .await
+    }
+
+    async fn reset(&mut self, se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.reset(h)).await
+    }
+
+    async fn enable(&mut self, se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.enable(h)).await
+    }
+
+    async fn disable(&mut self, se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.disable(h)).await
+    }
+
+    async fn destroy(&mut self, se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.destroy(h)).await
+    }
+
+    async fn candidate_clicked(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        index: u32,
+        button: u32,
+        state: u32,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| {
+            e.candidate_clicked(h, index, button, state)
+        })
+        .await
+    }
+
+    async fn page_up(&mut self, se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.page_up(h)).await
+    }
+
+    async fn page_down(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.page_down(h)).await
+    }
+
+    async fn cursor_up(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.cursor_up(h)).await
+    }
+
+    async fn cursor_down(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        self.call(&se, |e, h| e.cursor_down(h)).await
+    }
+
+    async fn set_surrounding_text(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        text: IBusText,
+        cursor_pos: u32,
+        anchor_pos: u32,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| {
+            e.set_surrounding_text(h, text, cursor_pos, anchor_pos)
+        })
+        .await
+    }
+
+    async fn set_content_type(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        purpose: IBusInputPurpose,
+        hints: IBusInputHints,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.set_content_type(h, purpose, hints))
+            .await
+    }
+
+    async fn property_activate(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        name: String,
+        state: IBusPropState,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.property_activate(h, name, state))
+            .await
+    }
+
+    async fn property_show(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        name: String,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.property_show(h, name)).await
+    }
+
+    async fn property_hide(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        name: String,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.property_hide(h, name)).await
+    }
+
+    async fn panel_extension_received(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        event: IBusExtensionEvent,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.panel_extension_received(h, event))
+            .await
+    }
+
+    async fn panel_extension_register_keys(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        keys: IBusExtensionKeys,
+    ) -> fdo::Result<()> {
+        self.call(&se, move |e, h| e.panel_extension_register_keys(h, keys))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ibus::testing::MockIBus;
+    use crate::ibus::{IBusEvent, IBusFactory, block_on};
+
+    /// 同步的码表: `a` -> `啊`, 其它字母进入 preedit
+    #[derive(Default)]
+    struct Table {
+        s: String,
+        destroyed: Option<Sender<()>>,
+    }
+
+    impl SyncIBusEngine for Table {
+        fn process_key_event(
+            &mut self,
+            h: &mut SyncEngineHandle,
+            keyval: Keysym,
+            keycode: KeyCode,
+            state: IBusModifierState,
+        ) -> bool {
+            if state.is_keyup() {
+                return false;
+            }
+            match keyval.key_char() {
+                Some('a') => {
+                    h.commit_text("啊");
+                }
+                Some(c) if c.is_ascii_lowercase() => {
+                    self.s.push(c);
+                    h.update_preedit_text(
+                        self.s.as_str(),
+                        self.s.len() as u32,
+                        true,
+                        IBusPreeditFocusMode::Clear,
+                    );
+                }
+                Some('.') => {
+                    h.forward_key_press_release(keyval, keycode, state);
+                }
+                Some('!') => panic!("测试 panic"),
+                _ => return false,
+            }
+            true
+        }
+
+        fn focus_out(&mut self, h: &mut SyncEngineHandle) {
+            h.update_auxiliary_text("", false);
+        }
+
+        fn destroy(&mut self, _h: &mut SyncEngineHandle) {
+            if let Some(d) = self.destroyed.take() {
+                let _ = d.send_blocking(());
+            }
+        }
+    }
+
+    struct TableFactory(Sender<()>);
+
+    impl IBusFactory<BlockingEngine<Table>> for TableFactory {
+        async fn create_engine(&mut self, _name: String) -> Result<BlockingEngine<Table>, String> {
+            Table {
+                destroyed: Some(self.0.clone()),
+                ..Default::default()
+            }
+            .blocking()
+            .map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn blocking_engine() {
+        block_on(async {
+            let (tx, rx) = async_channel::bounded(1);
+            let ibus = MockIBus::new(TableFactory(tx)).await.unwrap();
+            let mut e = ibus.create_engine("table").await.unwrap();
+
+            assert!(e.type_str("ba").await.unwrap());
+            assert!(!e.type_str("1").await.unwrap());
+            e.focus_out_id("/org/freedesktop/IBus/InputContext_1")
+                .await
+                .unwrap();
+            let events = e.events().await.unwrap();
+            assert_eq!(
+                events,
+                [
+                    IBusEvent::UpdatePreeditText {
+                        text: "b".into(),
+                        cursor_pos: 1,
+                        visible: true,
+                        mode: IBusPreeditFocusMode::Clear,
+                    },
+                    IBusEvent::CommitText("啊".into()),
+                    IBusEvent::UpdateAuxiliaryText {
+                        text: "".into(),
+                        visible: false,
+                    },
+                ]
+            );
+
+            // 一次回调发送多个信号
+            e.type_str(".").await.unwrap();
+            let events = e.events().await.unwrap();
+            assert_eq!(events.len(), 2);
+
+            // panic 之后, engine 线程继续运行
+            assert!(e.type_str("!").await.is_err());
+            assert!(e.type_str("a").await.unwrap());
+            assert_eq!(
+                e.events().await.unwrap(),
+                [IBusEvent::CommitText("啊".into())]
+            );
+
+            e.destroy().await.unwrap();
+            rx.recv().await.unwrap();
+        });
+    }
+}
@@ -5,11 +5,20 @@ use std::marker::PhantomData;
 use pm_bin::log::debug;
 use zbus::Connection;
 
-use super::{IBusEngine, IBusFactory};
+use super::{IBusComponent, IBusDaemon, IBusEngine, IBusFactory};
 
 use super::factory::{Factory, 注册factory};
 use super::init::{请求名称, 连接ibus};
 
+/// 注册方式 (重新连接时使用)
+#[derive(Debug, Clone)]
+enum 注册 {
+    /// 由 ibus-daemon 启动: 请求名称
+    名称(String),
+    /// 独立运行: 注册组件
+    组件(Box<IBusComponent>),
+}
+
 /// Abstract for the whole ibus
 #[derive(Debug, Clone)]
 pub struct IBus<T: IBusEngine, U: IBusFactory<T>> {
@@ -17,34 +26,101 @@ pub struct IBus<T: IBusEngine, U: IBusFactory<T>> {
     _u: PhantomData<U>,
 
     c: Connection,
+    addr: String,
+    r: 注册,
 }
 
 impl<T: IBusEngine + 'static, U: IBusFactory<T> + 'static> IBus<T, U> {
-    /// connect to ibus and init
-    ///
-    /// only support 1 engine now.
-    pub async fn new(addr: String, factory: U, name: String) -> Result<Self, Box<dyn Error>> {
-        let c = 连接ibus(addr).await?;
+    /// 连接 ibus, 注册 factory, 然后请求名称 (或注册组件)
+    async fn 连接(addr: String, factory: U, r: 注册) -> Result<Self, Box<dyn Error>> {
+        let c = 连接ibus(addr.clone()).await?;
         debug!("连接到 ibus 成功");
 
         {
-            let f = Factory::new(c.clone(), factory);
+            let f = Factory::new(factory);
             注册factory(&c, f).await?;
         }
         debug!("注册 factory 成功");
 
-        请求名称(&c, name.clone()).await?;
-        debug!("请求名称: {}", name);
+        match &r {
+            注册::名称(n) => {
+                请求名称(&c, n.clone()).await?;
+                debug!("请求名称: {}", n);
+            }
+            注册::组件(component) => {
+                IBusDaemon::new(&c)
+                    .await?
+                    .register_component(component)
+                    .await?;
+                debug!("注册组件: {}", component.name);
+            }
+        }
 
         Ok(Self {
             _t: PhantomData,
             _u: PhantomData,
             c,
+            addr,
+            r,
         })
     }
 
+    /// connect to ibus and init (launched by ibus-daemon)
+    ///
+    /// `name` is the name of the component, as in the component file installed in
+    /// `/usr/share/ibus/component/`.
+    ///
+    /// Each `CreateEngine` call of ibus-daemon creates a new engine (with `factory`), on its own
+    /// object path, until it is destroyed.
+    pub async fn new(addr: String, factory: U, name: String) -> Result<Self, Box<dyn Error>> {
+        Self::连接(addr, factory, 注册::名称(name)).await
+    }
+
+    /// connect to ibus and register `component` (started by the user)
+    ///
+    /// The engines of the component are available until this process exits, without
+    /// installing a component file or restarting ibus-daemon.
+    pub async fn standalone(
+        addr: String,
+        factory: U,
+        component: &IBusComponent,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::连接(addr, factory, 注册::组件(Box::new(component.clone()))).await
+    }
+
+    /// Connects again (to `addr`), and registers in the same way as before (name or component)
+    ///
+    /// The engines of the old connection are dropped.
+    pub async fn reconnect(&mut self, addr: String, factory: U) -> Result<(), Box<dyn Error>> {
+        *self = Self::连接(addr, factory, self.r.clone()).await?;
+        Ok(())
+    }
+
+    /// The address of ibus-daemon
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// The requested name (`None` for [`IBus::standalone`])
+    pub fn name(&self) -> Option<&str> {
+        match &self.r {
+            注册::名称(n) => Some(n),
+            注册::组件(_) => None,
+        }
+    }
+
+    /// Waits until the connection is closed (for example ibus-daemon exited or restarted)
+    pub async fn closed(&self) {
+        self.c.closed().await
+    }
+
     /// get D-Bus connection
     pub fn conn(&self) -> Connection {
         self.c.clone()
     }
+
+    /// client of the ibus-daemon (on the same connection)
+    pub async fn daemon(&self) -> zbus::Result<IBusDaemon> {
+        IBusDaemon::new(&self.c).await
+    }
 }
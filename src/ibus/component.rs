@@ -0,0 +1,239 @@
+//! `IBusComponent` 和 `IBusEngineDesc`: ibus-daemon 注册的组件 (输入法) 信息
+use zbus::zvariant::Value;
+
+use super::ibus_serde::{ibus_array_from, ibus_array_of, ibus_field, ibus_try_from};
+use super::{IBusAttachments, IBusErr, IBusSerializable};
+
+// 源文件: `ibus/src/ibusenginedesc.c`
+// 函数: `ibus_engine_desc_serialize()`
+//
+// (sa{sv}ssssssssussssssss)
+
+/// `IBusEngineDesc`: description of an input method engine
+///
+/// <https://ibus.github.io/docs/ibus-1.5/IBusEngineDesc.html>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IBusEngineDesc {
+    /// Unique name of the engine, used by `SetGlobalEngine`
+    pub name: String,
+    /// Name shown to the user
+    pub longname: String,
+    pub description: String,
+    /// Language code, for example `zh_CN`
+    pub language: String,
+    pub license: String,
+    pub author: String,
+    /// Icon name or file path
+    pub icon: String,
+    /// Keyboard layout, for example `us`
+    pub layout: String,
+    /// Higher rank engines are preferred (default engine of a language)
+    pub rank: u32,
+    pub hotkeys: String,
+    /// Short text shown in the panel (for example `喵`)
+    pub symbol: String,
+    /// Command line of the setup tool
+    pub setup: String,
+    pub layout_variant: String,
+    pub layout_option: String,
+    pub version: String,
+    pub textdomain: String,
+    pub icon_prop_key: String,
+    pub attachments: IBusAttachments,
+}
+
+impl IBusEngineDesc {
+    pub fn new(name: impl Into<String>, longname: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            longname: longname.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl IBusSerializable for IBusEngineDesc {
+    const NAME: &'static str = "IBusEngineDesc";
+    // 旧版本的 ibus 没有后面的字段 (从 hotkeys 开始)
+    const MIN_FIELDS: usize = 9;
+
+    fn attachments(&self) -> Option<&IBusAttachments> {
+        Some(&self.attachments)
+    }
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        let s = |s: &String| Value::new(s.clone());
+        vec![
+            s(&self.name),
+            s(&self.longname),
+            s(&self.description),
+            s(&self.language),
+            s(&self.license),
+            s(&self.author),
+            s(&self.icon),
+            s(&self.layout),
+            Value::new(self.rank),
+            s(&self.hotkeys),
+            s(&self.symbol),
+            s(&self.setup),
+            s(&self.layout_variant),
+            s(&self.layout_option),
+            s(&self.version),
+            s(&self.textdomain),
+            s(&self.icon_prop_key),
+        ]
+    }
+
+    fn from_fields(f: &[Value<'_>], attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        let s = |i: usize| {
+            if i < f.len() {
+                ibus_field::<String>(f, i)
+            } else {
+                Ok(String::new())
+            }
+        };
+        Ok(Self {
+            name: s(0)?,
+            longname: s(1)?,
+            description: s(2)?,
+            language: s(3)?,
+            license: s(4)?,
+            author: s(5)?,
+            icon: s(6)?,
+            layout: s(7)?,
+            rank: ibus_field(f, 8)?,
+            hotkeys: s(9)?,
+            symbol: s(10)?,
+            setup: s(11)?,
+            layout_variant: s(12)?,
+            layout_option: s(13)?,
+            version: s(14)?,
+            textdomain: s(15)?,
+            icon_prop_key: s(16)?,
+            attachments,
+        })
+    }
+}
+
+// 源文件: `ibus/src/ibuscomponent.c`
+// 函数: `ibus_component_serialize()`
+//
+// (sa{sv}ssssssssavav)
+
+/// `IBusComponent`: a program (such as an input method) registered to ibus-daemon
+///
+/// <https://ibus.github.io/docs/ibus-1.5/IBusComponent.html>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IBusComponent {
+    /// D-Bus name of the component, for example `org.freedesktop.IBus.PmimIBrus`
+    pub name: String,
+    pub description: String,
+    pub version: String,
+    pub license: String,
+    pub author: String,
+    pub homepage: String,
+    /// Command line to start the component
+    pub exec: String,
+    pub textdomain: String,
+    /// Engines provided by the component
+    pub engines: Vec<IBusEngineDesc>,
+    pub attachments: IBusAttachments,
+}
+
+impl IBusComponent {
+    pub fn new(name: impl Into<String>, exec: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            exec: exec.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl IBusSerializable for IBusComponent {
+    const NAME: &'static str = "IBusComponent";
+    const MIN_FIELDS: usize = 10;
+
+    fn attachments(&self) -> Option<&IBusAttachments> {
+        Some(&self.attachments)
+    }
+
+    // observed_paths 总是空的
+    fn fields(&self) -> Vec<Value<'static>> {
+        let s = |s: &String| Value::new(s.clone());
+        vec![
+            s(&self.name),
+            s(&self.description),
+            s(&self.version),
+            s(&self.license),
+            s(&self.author),
+            s(&self.homepage),
+            s(&self.exec),
+            s(&self.textdomain),
+            Value::new(Vec::<Value<'static>>::new()),
+            ibus_array_from(&self.engines),
+        ]
+    }
+
+    fn from_fields(f: &[Value<'_>], attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        Ok(Self {
+            name: ibus_field(f, 0)?,
+            description: ibus_field(f, 1)?,
+            version: ibus_field(f, 2)?,
+            license: ibus_field(f, 3)?,
+            author: ibus_field(f, 4)?,
+            homepage: ibus_field(f, 5)?,
+            exec: ibus_field(f, 6)?,
+            textdomain: ibus_field(f, 7)?,
+            engines: ibus_array_of(&f[9])?,
+            attachments,
+        })
+    }
+}
+
+ibus_try_from!(IBusEngineDesc, IBusComponent);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use zbus::zvariant::Structure;
+
+    #[test]
+    fn ibus_engine_desc_zvariant() {
+        let mut d = IBusEngineDesc::new("pmim", "胖喵拼音");
+        d.language = "zh_CN".to_string();
+        d.rank = 99;
+        let v = d.serialize();
+        assert_eq!(v.value_signature(), "(sa{sv}ssssssssussssssss)");
+        assert_eq!(IBusEngineDesc::try_from(&v).unwrap(), d);
+
+        let old = Value::new(Structure::from((
+            "IBusEngineDesc",
+            HashMap::<String, Value<'static>>::new(),
+            "pmim",
+            "胖喵拼音",
+            "",
+            "zh_CN",
+            "",
+            "",
+            "",
+            "us",
+            99u32,
+        )));
+        let o = IBusEngineDesc::try_from(&old).unwrap();
+        assert_eq!(o.layout, "us");
+        assert_eq!(o.symbol, "");
+    }
+
+    #[test]
+    fn ibus_component_zvariant() {
+        let mut c = IBusComponent::new("org.freedesktop.IBus.PmimIBrus", "/usr/bin/ibrus");
+        c.engines.push(IBusEngineDesc::new("pmim", "胖喵拼音"));
+        let v = c.serialize();
+        assert_eq!(v.value_signature(), "(sa{sv}ssssssssavav)");
+        assert_eq!(IBusComponent::try_from(&v).unwrap(), c);
+
+        assert!(IBusComponent::try_from(&IBusEngineDesc::default().serialize()).is_err());
+    }
+}
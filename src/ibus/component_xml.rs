@@ -0,0 +1,232 @@
+//! 组件的 XML 文件 (`/usr/share/ibus/component/*.xml`)
+//!
+//! 源文件: `ibus/src/ibuscomponent.c`, `ibus/src/ibusenginedesc.c`
+use quick_xml::{Reader, escape::escape, events::Event};
+
+use super::{IBusComponent, IBusEngineDesc, IBusErr};
+
+/// 组件的字段 (按照 ibus 的顺序)
+fn component_fields(c: &IBusComponent) -> [(&'static str, &String); 8] {
+    [
+        ("name", &c.name),
+        ("description", &c.description),
+        ("exec", &c.exec),
+        ("version", &c.version),
+        ("author", &c.author),
+        ("license", &c.license),
+        ("homepage", &c.homepage),
+        ("textdomain", &c.textdomain),
+    ]
+}
+
+fn component_field<'a>(c: &'a mut IBusComponent, name: &str) -> Option<&'a mut String> {
+    Some(match name {
+        "name" => &mut c.name,
+        "description" => &mut c.description,
+        "exec" => &mut c.exec,
+        "version" => &mut c.version,
+        "author" => &mut c.author,
+        "license" => &mut c.license,
+        "homepage" => &mut c.homepage,
+        "textdomain" => &mut c.textdomain,
+        _ => return None,
+    })
+}
+
+/// engine 的字段 (除了 rank)
+fn engine_fields(d: &IBusEngineDesc) -> [(&'static str, &String); 16] {
+    [
+        ("name", &d.name),
+        ("longname", &d.longname),
+        ("description", &d.description),
+        ("language", &d.language),
+        ("license", &d.license),
+        ("author", &d.author),
+        ("icon", &d.icon),
+        ("layout", &d.layout),
+        ("layout_variant", &d.layout_variant),
+        ("layout_option", &d.layout_option),
+        ("hotkeys", &d.hotkeys),
+        ("symbol", &d.symbol),
+        ("setup", &d.setup),
+        ("version", &d.version),
+        ("textdomain", &d.textdomain),
+        ("icon_prop_key", &d.icon_prop_key),
+    ]
+}
+
+fn engine_field<'a>(d: &'a mut IBusEngineDesc, name: &str) -> Option<&'a mut String> {
+    Some(match name {
+        "name" => &mut d.name,
+        "longname" => &mut d.longname,
+        "description" => &mut d.description,
+        "language" => &mut d.language,
+        "license" => &mut d.license,
+        "author" => &mut d.author,
+        "icon" => &mut d.icon,
+        "layout" => &mut d.layout,
+        "layout_variant" => &mut d.layout_variant,
+        "layout_option" => &mut d.layout_option,
+        "hotkeys" => &mut d.hotkeys,
+        "symbol" => &mut d.symbol,
+        "setup" => &mut d.setup,
+        "version" => &mut d.version,
+        "textdomain" => &mut d.textdomain,
+        "icon_prop_key" => &mut d.icon_prop_key,
+        _ => return None,
+    })
+}
+
+/// 输出 `<name>value</name>` (跳过空的值)
+fn 元素(o: &mut String, indent: usize, name: &str, value: &str) {
+    if !value.is_empty() {
+        o.push_str(&format!(
+            "{:indent$}<{name}>{}</{name}>\n",
+            "",
+            escape(value)
+        ));
+    }
+}
+
+impl IBusEngineDesc {
+    /// The `<engine>` element of a component file
+    pub fn to_xml(&self) -> String {
+        let mut o = String::from("<engine>\n");
+        for (k, v) in engine_fields(self) {
+            元素(&mut o, 2, k, v);
+        }
+        if self.rank != 0 {
+            元素(&mut o, 2, "rank", &self.rank.to_string());
+        }
+        o.push_str("</engine>\n");
+        o
+    }
+}
+
+impl IBusComponent {
+    /// The component file, to install in `/usr/share/ibus/component/`
+    ///
+    /// ```
+    /// use librush::ibus::{IBusComponent, IBusEngineDesc};
+    ///
+    /// let mut c = IBusComponent::new("org.fm_elpac.pmim", "/usr/lib/pmim/ibrus");
+    /// c.engines.push(IBusEngineDesc::new("pmim", "胖喵拼音"));
+    /// let x = c.to_xml();
+    /// assert_eq!(IBusComponent::from_xml(&x).unwrap(), c);
+    /// ```
+    pub fn to_xml(&self) -> String {
+        let mut o = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<component>\n");
+        for (k, v) in component_fields(self) {
+            元素(&mut o, 2, k, v);
+        }
+        o.push_str("  <engines>\n");
+        for e in self.engines.iter() {
+            for line in e.to_xml().lines() {
+                o.push_str(&format!("    {}\n", line));
+            }
+        }
+        o.push_str("  </engines>\n</component>\n");
+        o
+    }
+
+    /// Parses a component file (unknown elements are ignored)
+    pub fn from_xml(xml: &str) -> Result<Self, IBusErr> {
+        let mut r = Reader::from_str(xml);
+        r.config_mut().trim_text(true);
+        let e = |r: &Reader<&[u8]>, x: &dyn std::fmt::Display| {
+            IBusErr::new(format!(
+                "component xml (position {}): {}",
+                r.buffer_position(),
+                x
+            ))
+        };
+
+        let mut c = Self::default();
+        let mut path: Vec<String> = Vec::new();
+        let mut root = false;
+        loop {
+            let ev = r.read_event().map_err(|x| e(&r, &x))?;
+            match ev {
+                Event::Start(t) => {
+                    let n = String::from_utf8_lossy(t.name().as_ref()).to_string();
+                    if path.is_empty() {
+                        if n != "component" {
+                            return Err(e(&r, &format!("expected <component>, got <{}>", n)));
+                        }
+                        root = true;
+                    }
+                    if n == "engine" && path == ["component", "engines"] {
+                        c.engines.push(IBusEngineDesc::default());
+                    }
+                    path.push(n);
+                }
+                Event::End(_) => {
+                    path.pop();
+                }
+                Event::Text(t) => {
+                    let v = t.unescape().map_err(|x| e(&r, &x))?.into_owned();
+                    let p: Vec<&str> = path.iter().map(String::as_str).collect();
+                    match p[..] {
+                        ["component", k] => {
+                            if let Some(f) = component_field(&mut c, k) {
+                                *f = v;
+                            }
+                        }
+                        ["component", "engines", "engine", k] => {
+                            // 上面已经添加
+                            let d = c.engines.last_mut().unwrap();
+                            if k == "rank" {
+                                d.rank = v
+                                    .parse()
+                                    .map_err(|x| e(&r, &format!("rank {}: {}", v, x)))?;
+                            } else if let Some(f) = engine_field(d, k) {
+                                *f = v;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        if !root {
+            return Err(e(&r, &"missing <component>"));
+        }
+        Ok(c)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ibus_component_xml() {
+        let x = r#"<?xml version="1.0" encoding="utf-8"?>
+<!-- 注释 -->
+<component>
+  <name>org.fm_elpac.pmim</name>
+  <exec>/usr/lib/pmim/ibrus --flatpak</exec>
+  <observed-paths><path>~/.config</path></observed-paths>
+  <engines>
+    <engine>
+      <name>pmim</name>
+      <longname>胖喵 &amp; 拼音</longname>
+      <rank>99</rank>
+    </engine>
+  </engines>
+</component>
+"#;
+        let c = IBusComponent::from_xml(x).unwrap();
+        assert_eq!(c.exec, "/usr/lib/pmim/ibrus --flatpak");
+        assert_eq!(c.engines.len(), 1);
+        assert_eq!(c.engines[0].longname, "胖喵 & 拼音");
+        assert_eq!(c.engines[0].rank, 99);
+        assert!(c.to_xml().contains("<longname>胖喵 &amp; 拼音</longname>"));
+        assert_eq!(IBusComponent::from_xml(&c.to_xml()).unwrap(), c);
+
+        assert!(IBusComponent::from_xml("<engines></engines>").is_err());
+        assert!(IBusComponent::from_xml("<component><engines><engine><rank>x</rank>").is_err());
+    }
+}
@@ -0,0 +1,444 @@
+//! `EngineContext`: 在回调以外使用 engine (例如后台任务)
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use xkeysym::{KeyCode, Keysym};
+use zbus::{Connection, object_server::SignalEmitter, zvariant::ObjectPath};
+
+use super::{
+    BoxedIBusEngine, IBusCapabilite, IBusEngineBackend, IBusExtensionEvent, IBusInputHints,
+    IBusInputPurpose, IBusModifierState, IBusPreeditFocusMode, IBusPropList, IBusProperty,
+    IBusSurroundingText, IBusText, LookupTable,
+};
+
+/// engine 的状态 (客户端最后发送的值)
+#[derive(Debug)]
+pub(crate) struct EngineState {
+    pub caps: IBusCapabilite,
+    pub surrounding_text: IBusSurroundingText,
+    /// (purpose, hints)
+    pub content_type: (u32, u32),
+    /// engine 需要 surrounding text (`RequireSurroundingText`)
+    pub active_surrounding_text: bool,
+    pub destroyed: bool,
+}
+
+impl Default for EngineState {
+    fn default() -> Self {
+        Self {
+            caps: IBusCapabilite::new_with_raw_value(0),
+            surrounding_text: IBusSurroundingText::default(),
+            content_type: (0, 0),
+            active_surrounding_text: false,
+            destroyed: false,
+        }
+    }
+}
+
+/// engine 的键: (连接, object path)
+///
+/// 同一个 object path 可能属于不同的连接,
+/// 例如重新连接以后的旧连接, 或者测试时的多个 `MockIBus`.
+type 键 = (String, String);
+
+/// 连接使用 server guid 和 unique name 区分 (点对点连接没有 unique name)
+fn 键(c: &Connection, path: &str) -> 键 {
+    let n = c.unique_name().map(|n| n.as_str()).unwrap_or_default();
+    (format!("{} {}", c.server_guid(), n), path.to_string())
+}
+
+/// 全部 engine 的状态
+///
+/// `IBusEngineBackend` 的 getter 只有 `SignalEmitter`, 在这里查找 (不锁定 engine).
+/// 创建 engine 时添加, 销毁时删除, 所以也是每个连接上没有销毁的 engine.
+/// 状态属于 engine 对象 (`EngineContext`), 这里只有 `Weak`: 连接关闭以后也不会保留状态.
+static 全部状态: Mutex<BTreeMap<键, Weak<Mutex<EngineState>>>> = Mutex::new(BTreeMap::new());
+
+fn 状态表() -> MutexGuard<'static, BTreeMap<键, Weak<Mutex<EngineState>>>> {
+    全部状态.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 删除 engine 的状态 (销毁 engine 以后)
+pub(crate) fn 删除状态(c: &Connection, path: &str) {
+    状态表().remove(&键(c, path));
+}
+
+/// 连接上没有销毁的 engine (object path)
+pub(crate) fn engine列表(c: &Connection) -> Vec<String> {
+    let (k, _) = 键(c, "");
+    let mut t = 状态表();
+    t.retain(|_, s| s.strong_count() > 0);
+    t.keys()
+        .filter(|(c, _)| *c == k)
+        .map(|(_, p)| p.clone())
+        .collect()
+}
+
+/// 读取 engine 的状态
+pub(crate) fn 读取状态<R>(
+    se: &SignalEmitter<'_>,
+    f: impl FnOnce(&EngineState) -> R,
+) -> zbus::Result<R> {
+    修改状态(se, |s| f(s))
+}
+
+/// 修改 engine 的状态
+pub(crate) fn 修改状态<R>(
+    se: &SignalEmitter<'_>,
+    f: impl FnOnce(&mut EngineState) -> R,
+) -> zbus::Result<R> {
+    let s = 状态表()
+        .get(&键(se.connection(), se.path()))
+        .and_then(Weak::upgrade)
+        .ok_or_else(|| zbus::Error::Failure(format!("unknown engine: {}", se.path())))?;
+    let mut s = s.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(f(&mut s))
+}
+
+/// A handle to one engine, usable outside the callbacks
+///
+/// It is cheap to clone and can be moved to a background task, for example to commit the
+/// result of a slow dictionary lookup. The engine gets it from
+/// [`IBusEngine::set_context`](super::IBusEngine::set_context).
+///
+/// Same methods as [`IBusEngineBackend`], but they fail after the engine is destroyed. The
+/// getters ([`Self::capabilities`]...) do not lock the engine, so they can also be called from
+/// a callback.
+#[derive(Debug, Clone)]
+pub struct EngineContext {
+    se: SignalEmitter<'static>,
+    s: Arc<Mutex<EngineState>>,
+}
+
+impl EngineContext {
+    pub(crate) fn new(c: &Connection, object_path: String) -> zbus::Result<Self> {
+        let s = Arc::new(Mutex::new(EngineState::default()));
+        {
+            let mut t = 状态表();
+            // 删除已经关闭的连接的 engine
+            t.retain(|_, s| s.strong_count() > 0);
+            t.insert(键(c, &object_path), Arc::downgrade(&s));
+        }
+        Ok(Self {
+            se: SignalEmitter::new(c, object_path)?,
+            s,
+        })
+    }
+
+    pub(crate) fn state(&self) -> MutexGuard<'_, EngineState> {
+        self.s.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 检查 engine 是否已经销毁
+    ///
+    /// 信号与 engine 的类型无关: 使用 `BoxedIBusEngine` 的方法发送.
+    fn se(&self) -> zbus::Result<&SignalEmitter<'static>> {
+        if self.state().destroyed {
+            return Err(zbus::Error::Failure(format!(
+                "engine is destroyed: {}",
+                self.se.path()
+            )));
+        }
+        Ok(&self.se)
+    }
+
+    /// The object path of the engine
+    pub fn path(&self) -> &ObjectPath<'static> {
+        self.se.path()
+    }
+
+    /// The engine was destroyed by ibus-daemon (after
+    /// [`IBusEngine::destroy`](super::IBusEngine::destroy))
+    pub fn is_destroyed(&self) -> bool {
+        self.state().destroyed
+    }
+
+    /// See [`IBusEngineBackend::commit_text`]
+    pub async fn commit_text(&self, text: impl Into<IBusText> + Send) -> zbus::Result<()> {
+        BoxedIBusEngine::commit_text(self.se()?, text).await
+    }
+
+    /// See [`IBusEngineBackend::update_lookup_table`]
+    pub async fn update_lookup_table(
+        &self,
+        table: &LookupTable,
+        visible: bool,
+    ) -> zbus::Result<()> {
+        BoxedIBusEngine::update_lookup_table(self.se()?, table, visible).await
+    }
+
+    /// See [`IBusEngineBackend::update_preedit_text`]
+    pub async fn update_preedit_text(
+        &self,
+        text: impl Into<IBusText> + Send,
+        cursor_pos: u32,
+        visible: bool,
+        mode: IBusPreeditFocusMode,
+    ) -> zbus::Result<()> {
+        BoxedIBusEngine::update_preedit_text(self.se()?, text, cursor_pos, visible, mode).await
+    }
+
+    /// See [`IBusEngineBackend::update_auxiliary_text`]
+    pub async fn update_auxiliary_text(
+        &self,
+        text: impl Into<IBusText> + Send,
+        visible: bool,
+    ) -> zbus::Result<()> {
+        BoxedIBusEngine::update_auxiliary_text(self.se()?, text, visible).await
+    }
+
+    /// See [`IBusEngineBackend::register_properties`]
+    pub async fn register_properties(&self, props: &IBusPropList) -> zbus::Result<()> {
+        BoxedIBusEngine::register_properties(self.se()?, props).await
+    }
+
+    /// See [`IBusEngineBackend::update_property`]
+    pub async fn update_property(&self, prop: &IBusProperty) -> zbus::Result<()> {
+        BoxedIBusEngine::update_property(self.se()?, prop).await
+    }
+
+    /// See [`IBusEngineBackend::forward_key_event`]
+    pub async fn forward_key_event(
+        &self,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> zbus::Result<()> {
+        BoxedIBusEngine::forward_key_event(self.se()?, keyval, keycode, state).await
+    }
+
+    /// See [`IBusEngineBackend::forward_key_press_release`]
+    pub async fn forward_key_press_release(
+        &self,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> zbus::Result<()> {
+        BoxedIBusEngine::forward_key_press_release(self.se()?, keyval, keycode, state).await
+    }
+
+    /// See [`IBusEngineBackend::panel_extension`]
+    pub async fn panel_extension(&self, event: &IBusExtensionEvent) -> zbus::Result<()> {
+        BoxedIBusEngine::panel_extension(self.se()?, event).await
+    }
+
+    /// See [`IBusEngineBackend::delete_surrounding_text`]
+    pub async fn delete_surrounding_text(&self, offset: i32, n_chars: u32) -> zbus::Result<()> {
+        BoxedIBusEngine::delete_surrounding_text(self.se()?, offset, n_chars).await
+    }
+
+    /// See [`IBusEngineBackend::require_surrounding_text`]
+    pub async fn require_surrounding_text(&self) -> zbus::Result<()> {
+        BoxedIBusEngine::require_surrounding_text(self.se()?).await
+    }
+
+    /// The capabilities of the client, as last received by
+    /// [`IBusEngine::set_capabilities`](super::IBusEngine::set_capabilities)
+    pub fn capabilities(&self) -> IBusCapabilite {
+        self.state().caps
+    }
+
+    /// The surrounding text, as last received by
+    /// [`IBusEngine::set_surrounding_text`](super::IBusEngine::set_surrounding_text)
+    pub fn surrounding_text(&self) -> IBusSurroundingText {
+        self.state().surrounding_text.clone()
+    }
+
+    /// The content type, as last received by
+    /// [`IBusEngine::set_content_type`](super::IBusEngine::set_content_type)
+    pub fn content_type(&self) -> (IBusInputPurpose, IBusInputHints) {
+        let (p, h) = self.state().content_type;
+        (p.into(), IBusInputHints::new_with_raw_value(h))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ibus::testing::MockIBus;
+    use crate::ibus::{IBusEngine, IBusEvent, IBusFactory, block_on, spawn};
+    use async_channel::Sender;
+    use zbus::{ObjectServer, fdo};
+
+    /// 按键以后在后台任务提交文本 (例如云输入)
+    struct Cloud {
+        ctx: Option<EngineContext>,
+        c: Sender<EngineContext>,
+    }
+
+    impl IBusEngine for Cloud {
+        fn set_context(&mut self, ctx: EngineContext) {
+            self.ctx = Some(ctx);
+        }
+
+        async fn process_key_event(
+            &mut self,
+            _se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            keyval: Keysym,
+            _keycode: KeyCode,
+            state: IBusModifierState,
+        ) -> fdo::Result<bool> {
+            if state.is_keyup() {
+                return Ok(false);
+            }
+            let ctx = self.ctx.clone().unwrap();
+            // 不锁定 engine
+            assert!(!ctx.capabilities().preedit_text());
+            let c = self.c.clone();
+            spawn(async move {
+                ctx.commit_text(format!("云{}", keyval.key_char().unwrap()))
+                    .await
+                    .unwrap();
+                c.send(ctx).await.unwrap();
+            });
+            Ok(true)
+        }
+    }
+
+    /// 在回调里读取客户端的状态 (使用 `BoxedIBusEngine`)
+    struct Client;
+
+    impl IBusEngine for Client {
+        async fn focus_in(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+        ) -> fdo::Result<()> {
+            Self::require_surrounding_text(&se).await?;
+            Ok(())
+        }
+
+        async fn process_key_event(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            keyval: Keysym,
+            _keycode: KeyCode,
+            state: IBusModifierState,
+        ) -> fdo::Result<bool> {
+            if state.is_keyup() {
+                return Ok(false);
+            }
+            // 密码: 不捕捉按键
+            if Self::content_type(&se).await?.0.is_hidden() {
+                return Ok(false);
+            }
+            // 退格: 删除光标前的一个字
+            if keyval == Keysym::BackSpace {
+                let t = Self::surrounding_text(&se).await?;
+                if t.cursor_pos > 0 {
+                    Self::delete_surrounding_text(&se, -1, 1).await?;
+                }
+                return Ok(true);
+            }
+            // 不支持 preedit 时使用 auxiliary text
+            if Self::capabilities(&se).await?.preedit_text() {
+                Self::update_preedit_text(&se, "a", 1, true, IBusPreeditFocusMode::Clear).await?;
+            } else {
+                Self::update_auxiliary_text(&se, "a", true).await?;
+            }
+            Ok(true)
+        }
+    }
+
+    struct ClientFactory;
+
+    impl IBusFactory<BoxedIBusEngine> for ClientFactory {
+        async fn create_engine(&mut self, _name: String) -> Result<BoxedIBusEngine, String> {
+            Ok(Client.boxed())
+        }
+    }
+
+    #[test]
+    fn backend_getters_in_callback() {
+        block_on(async {
+            let ibus = MockIBus::new(ClientFactory).await.unwrap();
+            let mut e = ibus.create_engine("client").await.unwrap();
+
+            e.type_str("a").await.unwrap();
+            let caps = IBusCapabilite::new_with_raw_value(0).with_preedit_text(true);
+            e.set_capabilities(caps).await.unwrap();
+            e.type_str("a").await.unwrap();
+            assert_eq!(
+                e.events().await.unwrap(),
+                [
+                    IBusEvent::UpdateAuxiliaryText {
+                        text: "a".into(),
+                        visible: true,
+                    },
+                    IBusEvent::UpdatePreeditText {
+                        text: "a".into(),
+                        cursor_pos: 1,
+                        visible: true,
+                        mode: IBusPreeditFocusMode::Clear,
+                    },
+                ]
+            );
+
+            // 需要 surrounding text
+            assert!(!e.active_surrounding_text().await.unwrap());
+            e.focus_in().await.unwrap();
+            assert!(e.active_surrounding_text().await.unwrap());
+            assert_eq!(
+                e.events().await.unwrap(),
+                [IBusEvent::RequireSurroundingText]
+            );
+
+            let bs = IBusModifierState::new_with_raw_value(0);
+            e.process_key_event(Keysym::BackSpace, KeyCode::new(22), bs)
+                .await
+                .unwrap();
+            e.set_surrounding_text("喵", 1, 1).await.unwrap();
+            e.process_key_event(Keysym::BackSpace, KeyCode::new(22), bs)
+                .await
+                .unwrap();
+            assert_eq!(
+                e.events().await.unwrap(),
+                [IBusEvent::DeleteSurroundingText {
+                    offset: -1,
+                    n_chars: 1,
+                }]
+            );
+
+            let hints = IBusInputHints::new_with_raw_value(0);
+            e.set_content_type(IBusInputPurpose::Password, hints)
+                .await
+                .unwrap();
+            assert!(!e.type_str("a").await.unwrap());
+        });
+    }
+
+    struct CloudFactory(Sender<EngineContext>);
+
+    impl IBusFactory<Cloud> for CloudFactory {
+        async fn create_engine(&mut self, _name: String) -> Result<Cloud, String> {
+            Ok(Cloud {
+                ctx: None,
+                c: self.0.clone(),
+            })
+        }
+    }
+
+    #[test]
+    fn engine_context() {
+        block_on(async {
+            let (tx, rx) = async_channel::unbounded();
+            let ibus = MockIBus::new(CloudFactory(tx)).await.unwrap();
+            let mut e = ibus.create_engine("cloud").await.unwrap();
+
+            assert!(e.type_str("a").await.unwrap());
+            let ctx = rx.recv().await.unwrap();
+            assert_eq!(ctx.path().as_str(), e.path().as_str());
+            assert_eq!(
+                e.next_event().await.unwrap(),
+                IBusEvent::CommitText("云a".into())
+            );
+
+            // engine 销毁以后失效
+            e.destroy().await.unwrap();
+            assert!(ctx.is_destroyed());
+            assert!(ctx.commit_text("云").await.is_err());
+        });
+    }
+}
@@ -0,0 +1,155 @@
+//! `org.freedesktop.IBus`: ibus-daemon 的接口 (客户端)
+use futures_lite::{Stream, StreamExt};
+use zbus::{
+    Connection, proxy,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+use super::{IBusComponent, IBusEngineDesc, IBusInputContext, IBusSerializable};
+
+// 源文件: `ibus/bus/ibusimpl.c`
+//
+// <node>
+//   <interface name='org.freedesktop.IBus'>
+//     <property name='Address' type='s' access='read' />
+//     <property name='CurrentInputContext' type='o' access='read' />
+//     <property name='Engines' type='av' access='read' />
+//     <property name='GlobalEngine' type='v' access='read' />
+//     <property name='PreloadEngines' type='as' access='write' />
+//     <property name='EmbedPreeditText' type='b' access='readwrite' />
+//     <method name='CreateInputContext'>
+//       <arg direction='in'  type='s' name='client_name' />
+//       <arg direction='out' type='o' name='object_path' />
+//     </method>
+//     <method name='RegisterComponent'>
+//       <arg direction='in'  type='v' name='component' />
+//     </method>
+//     <method name='GetEnginesByNames'>
+//       <arg direction='in'  type='as' name='names' />
+//       <arg direction='out' type='av' name='engines' />
+//     </method>
+//     <method name='Exit'>
+//       <arg direction='in'  type='b' name='restart' />
+//     </method>
+//     <method name='Ping'>
+//       <arg direction='in'  type='v' name='data' />
+//       <arg direction='out' type='v' name='data' />
+//     </method>
+//     <method name='SetGlobalEngine'>
+//       <arg direction='in'  type='s' name='engine_name' />
+//     </method>
+//     <signal name='RegistryChanged'>
+//     </signal>
+//     <signal name='GlobalEngineChanged'>
+//       <arg type='s' name='engine_name' />
+//     </signal>
+//     ...
+//   </interface>
+// </node>
+//
+// `ListEngines`, `ListActiveEngines` 和 `GetGlobalEngine` 已经废弃, 但是仍然可用
+#[proxy(
+    interface = "org.freedesktop.IBus",
+    default_service = "org.freedesktop.IBus",
+    default_path = "/org/freedesktop/IBus",
+    gen_blocking = false
+)]
+trait Daemon {
+    fn list_engines(&self) -> zbus::Result<Vec<OwnedValue>>;
+
+    fn list_active_engines(&self) -> zbus::Result<Vec<OwnedValue>>;
+
+    fn get_engines_by_names(&self, names: &[&str]) -> zbus::Result<Vec<OwnedValue>>;
+
+    fn set_global_engine(&self, engine_name: &str) -> zbus::Result<()>;
+
+    fn get_global_engine(&self) -> zbus::Result<OwnedValue>;
+
+    fn register_component(&self, component: &Value<'_>) -> zbus::Result<()>;
+
+    fn create_input_context(&self, client_name: &str) -> zbus::Result<OwnedObjectPath>;
+
+    fn exit(&self, restart: bool) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn global_engine_changed(&self, engine_name: String) -> zbus::Result<()>;
+}
+
+/// 解码 `av` (IBusEngineDesc)
+fn engine_list(v: Vec<OwnedValue>) -> zbus::Result<Vec<IBusEngineDesc>> {
+    let mut o = Vec::with_capacity(v.len());
+    for i in v.iter() {
+        o.push(IBusEngineDesc::try_from(&**i)?);
+    }
+    Ok(o)
+}
+
+/// Client of the ibus-daemon (D-Bus interface: `org.freedesktop.IBus`)
+///
+/// <https://ibus.github.io/docs/ibus-1.5/IBusBus.html>
+#[derive(Debug, Clone)]
+pub struct IBusDaemon {
+    p: DaemonProxy<'static>,
+}
+
+impl IBusDaemon {
+    /// `c`: connection to the ibus bus (see [`crate::ibus::get_ibus_addr`])
+    pub async fn new(c: &Connection) -> zbus::Result<Self> {
+        let p = DaemonProxy::new(c).await?;
+        Ok(Self { p })
+    }
+
+    /// All engines known to ibus-daemon
+    pub async fn list_engines(&self) -> zbus::Result<Vec<IBusEngineDesc>> {
+        engine_list(self.p.list_engines().await?)
+    }
+
+    /// Engines enabled by the user (preload engines)
+    pub async fn list_active_engines(&self) -> zbus::Result<Vec<IBusEngineDesc>> {
+        engine_list(self.p.list_active_engines().await?)
+    }
+
+    /// Unknown names are skipped
+    pub async fn get_engines_by_names(&self, names: &[&str]) -> zbus::Result<Vec<IBusEngineDesc>> {
+        engine_list(self.p.get_engines_by_names(names).await?)
+    }
+
+    /// Switch the current engine (for example `pmim`)
+    pub async fn set_global_engine(&self, name: &str) -> zbus::Result<()> {
+        self.p.set_global_engine(name).await
+    }
+
+    /// The current engine, or error if there is none
+    pub async fn get_global_engine(&self) -> zbus::Result<IBusEngineDesc> {
+        let v = self.p.get_global_engine().await?;
+        Ok(IBusEngineDesc::try_from(&*v)?)
+    }
+
+    pub async fn register_component(&self, component: &IBusComponent) -> zbus::Result<()> {
+        self.p.register_component(&component.serialize()).await
+    }
+
+    /// Returns the object path of the new input context
+    pub async fn create_input_context(&self, client_name: &str) -> zbus::Result<OwnedObjectPath> {
+        self.p.create_input_context(client_name).await
+    }
+
+    /// Creates an input context, as an application would do
+    pub async fn input_context(&self, client_name: &str) -> zbus::Result<IBusInputContext> {
+        let path = self.p.create_input_context(client_name).await?;
+        IBusInputContext::new(self.p.inner().connection(), path).await
+    }
+
+    /// Stops (or restarts) ibus-daemon
+    pub async fn exit(&self, restart: bool) -> zbus::Result<()> {
+        self.p.exit(restart).await
+    }
+
+    /// Stream of the names of the new global engine (signal `GlobalEngineChanged`)
+    pub async fn receive_global_engine_changed(
+        &self,
+    ) -> zbus::Result<impl Stream<Item = String> + use<>> {
+        let s = self.p.receive_global_engine_changed().await?;
+        Ok(s.filter_map(|m| m.args().ok().map(|a| a.engine_name().clone())))
+    }
+}
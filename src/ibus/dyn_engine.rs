@@ -0,0 +1,291 @@
+//! 动态分发的 engine (`Box<dyn DynIBusEngine>`)
+//!
+//! [`IBusEngine`] 使用 `impl Future`, 不能用作 `dyn`.
+use std::future::Future;
+use std::pin::Pin;
+
+use xkeysym::{KeyCode, Keysym};
+use zbus::{ObjectServer, fdo, object_server::SignalEmitter};
+
+use super::{
+    EngineContext, IBusCapabilite, IBusEngine, IBusExtensionEvent, IBusExtensionKeys,
+    IBusInputHints, IBusInputPurpose, IBusModifierState, IBusPropState, IBusStroke, IBusText,
+};
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// [`IBusEngine`] 的全部回调 (除了 `set_context`): 名称, 参数 (`se` 和 `server` 以后), 返回值
+///
+/// 下面的 3 个实现都使用这个列表, 添加回调时只需要修改这里.
+macro_rules! 回调列表 {
+    ($m:ident) => {
+        $m! {
+            process_key_event(keyval: Keysym, keycode: KeyCode, state: IBusModifierState) -> bool;
+            process_hand_writing_event(stroke: IBusStroke) -> ();
+            cancel_hand_writing(n_strokes: u32) -> ();
+            set_cursor_location(x: i32, y: i32, w: i32, h: i32) -> ();
+            set_capabilities(caps: IBusCapabilite) -> ();
+            focus_in() -> ();
+            focus_out() -> ();
+            focus_in_id(object_path: String, client: String) -> ();
+            focus_out_id(object_path: String) -> ();
+            reset() -> ();
+            enable() -> ();
+            disable() -> ();
+            destroy() -> ();
+            candidate_clicked(index: u32, button: u32, state: u32) -> ();
+            page_up() -> ();
+            page_down() -> ();
+            cursor_up() -> ();
+            cursor_down() -> ();
+            set_surrounding_text(text: IBusText, cursor_pos: u32, anchor_pos: u32) -> ();
+            set_content_type(purpose: IBusInputPurpose, hints: IBusInputHints) -> ();
+            property_activate(name: String, state: IBusPropState) -> ();
+            property_show(name: String) -> ();
+            property_hide(name: String) -> ();
+            panel_extension_received(event: IBusExtensionEvent) -> ();
+            panel_extension_register_keys(keys: IBusExtensionKeys) -> ();
+        }
+    };
+}
+
+/// `DynIBusEngine` 的方法声明
+macro_rules! 声明 {
+    ($($name:ident($($arg:ident: $t:ty),*) -> $r:ty;)*) => {$(
+        fn $name<'a>(
+            &'a mut self,
+            se: SignalEmitter<'a>,
+            server: &'a ObjectServer,
+            $($arg: $t),*
+        ) -> BoxFuture<'a, fdo::Result<$r>>;
+    )*};
+}
+
+/// `DynIBusEngine` 的方法: 调用 `IBusEngine`
+macro_rules! 转换 {
+    ($($name:ident($($arg:ident: $t:ty),*) -> $r:ty;)*) => {$(
+        fn $name<'a>(
+            &'a mut self,
+            se: SignalEmitter<'a>,
+            server: &'a ObjectServer,
+            $($arg: $t),*
+        ) -> BoxFuture<'a, fdo::Result<$r>> {
+            Box::pin(IBusEngine::$name(self, se, server, $($arg),*))
+        }
+    )*};
+}
+
+/// `BoxedIBusEngine` 的方法: 调用 `DynIBusEngine`
+macro_rules! 转发 {
+    ($($name:ident($($arg:ident: $t:ty),*) -> $r:ty;)*) => {$(
+        async fn $name(
+            &mut self,
+            se: SignalEmitter<'_>,
+            server: &ObjectServer,
+            $($arg: $t),*
+        ) -> fdo::Result<$r> {
+            DynIBusEngine::$name(self.as_mut(), se, server, $($arg),*).await
+        }
+    )*};
+}
+
+/// Object-safe version of [`IBusEngine`]
+///
+/// It is implemented for every [`IBusEngine`], so there is no need to implement it directly.
+/// Use [`BoxedIBusEngine`] to return different engine types from one
+/// [`crate::ibus::IBusFactory`].
+pub trait DynIBusEngine: Send + Sync {
+    fn set_context(&mut self, ctx: EngineContext);
+
+    回调列表!(声明);
+}
+
+impl<T: IBusEngine> DynIBusEngine for T {
+    fn set_context(&mut self, ctx: EngineContext) {
+        IBusEngine::set_context(self, ctx)
+    }
+
+    回调列表!(转换);
+}
+
+/// An engine of any type
+///
+/// ```
+/// use librush::ibus::{BoxedIBusEngine, IBus, IBusEngine, IBusFactory};
+///
+/// struct Pinyin;
+/// impl IBusEngine for Pinyin {}
+///
+/// struct English;
+/// impl IBusEngine for English {}
+///
+/// struct Factory;
+///
+/// impl IBusFactory<BoxedIBusEngine> for Factory {
+///     async fn create_engine(&mut self, name: String) -> Result<BoxedIBusEngine, String> {
+///         match name.as_str() {
+///             "pinyin" => Ok(Pinyin.boxed()),
+///             "english" => Ok(English.boxed()),
+///             _ => Err(format!("unknown name: {}", name)),
+///         }
+///     }
+/// }
+///
+/// async fn run(addr: String) {
+///     let _b = IBus::new(addr, Factory, "org.example.im".to_string()).await;
+/// }
+/// ```
+pub type BoxedIBusEngine = Box<dyn DynIBusEngine>;
+
+impl IBusEngine for BoxedIBusEngine {
+    /// Already boxed: returns itself
+    fn boxed(self) -> BoxedIBusEngine {
+        self
+    }
+
+    fn set_context(&mut self, ctx: EngineContext) {
+        DynIBusEngine::set_context(self.as_mut(), ctx)
+    }
+
+    回调列表!(转发);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ibus::testing::MockIBus;
+    use crate::ibus::{IBusExtensionKeys, IBusFactory, block_on};
+    use async_channel::Sender;
+
+    /// 记录调用的回调
+    struct Record(Sender<&'static str>);
+
+    /// 每个回调: 记录名称
+    macro_rules! 记录 {
+        ($($name:ident($($arg:ident: $t:ty),*) -> $r:ty;)*) => {$(
+            async fn $name(
+                &mut self,
+                _se: SignalEmitter<'_>,
+                _server: &ObjectServer,
+                $(_: $t),*
+            ) -> fdo::Result<$r> {
+                self.0.try_send(stringify!($name)).unwrap();
+                Ok(Default::default())
+            }
+        )*};
+    }
+
+    impl IBusEngine for Record {
+        fn set_context(&mut self, _ctx: EngineContext) {
+            self.0.try_send("set_context").unwrap();
+        }
+
+        回调列表!(记录);
+    }
+
+    struct RecordFactory(Sender<&'static str>);
+
+    impl IBusFactory<BoxedIBusEngine> for RecordFactory {
+        async fn create_engine(&mut self, _name: String) -> Result<BoxedIBusEngine, String> {
+            Ok(Record(self.0.clone()).boxed())
+        }
+    }
+
+    #[test]
+    fn boxed_engine_forwards_callbacks() {
+        block_on(async {
+            let (tx, rx) = async_channel::unbounded();
+            let ibus = MockIBus::new(RecordFactory(tx.clone())).await.unwrap();
+            let mut e = ibus.create_engine("record").await.unwrap();
+
+            let s = IBusModifierState::new_with_raw_value(0);
+            e.process_key_event(Keysym::a, KeyCode::new(38), s)
+                .await
+                .unwrap();
+            e.process_hand_writing_event(&IBusStroke::from_coordinates(&[0.0, 0.0]))
+                .await
+                .unwrap();
+            e.cancel_hand_writing(0).await.unwrap();
+            e.set_cursor_location(0, 0, 1, 1).await.unwrap();
+            e.set_capabilities(IBusCapabilite::new_with_raw_value(0))
+                .await
+                .unwrap();
+            e.focus_in().await.unwrap();
+            e.focus_out().await.unwrap();
+            e.focus_in_id("/org/freedesktop/IBus/InputContext_1", "test")
+                .await
+                .unwrap();
+            e.focus_out_id("/org/freedesktop/IBus/InputContext_1")
+                .await
+                .unwrap();
+            e.reset().await.unwrap();
+            e.enable().await.unwrap();
+            e.disable().await.unwrap();
+            e.candidate_clicked(0, 1, 0).await.unwrap();
+            e.page_up().await.unwrap();
+            e.page_down().await.unwrap();
+            e.cursor_up().await.unwrap();
+            e.cursor_down().await.unwrap();
+            e.set_surrounding_text("", 0, 0).await.unwrap();
+            e.set_content_type(
+                IBusInputPurpose::FreeForm,
+                IBusInputHints::new_with_raw_value(0),
+            )
+            .await
+            .unwrap();
+            e.property_activate("p", IBusPropState::Checked)
+                .await
+                .unwrap();
+            e.property_show("p").await.unwrap();
+            e.property_hide("p").await.unwrap();
+            e.panel_extension_received(&IBusExtensionEvent::new("emoji", false))
+                .await
+                .unwrap();
+            e.panel_extension_register_keys(&IBusExtensionKeys::new())
+                .await
+                .unwrap();
+            e.destroy().await.unwrap();
+
+            let mut n = Vec::new();
+            while let Ok(i) = rx.try_recv() {
+                n.push(i);
+            }
+            assert_eq!(
+                n,
+                [
+                    "set_context",
+                    "process_key_event",
+                    "process_hand_writing_event",
+                    "cancel_hand_writing",
+                    "set_cursor_location",
+                    "set_capabilities",
+                    "focus_in",
+                    "focus_out",
+                    "focus_in_id",
+                    "focus_out_id",
+                    "reset",
+                    "enable",
+                    "disable",
+                    "candidate_clicked",
+                    "page_up",
+                    "page_down",
+                    "cursor_up",
+                    "cursor_down",
+                    "set_surrounding_text",
+                    "set_content_type",
+                    "property_activate",
+                    "property_show",
+                    "property_hide",
+                    "panel_extension_received",
+                    "panel_extension_register_keys",
+                    "destroy",
+                ]
+            );
+
+            // 已经是 BoxedIBusEngine: 不再装箱
+            let b = Record(tx).boxed();
+            let p = &*b as *const dyn DynIBusEngine;
+            assert!(std::ptr::addr_eq(p, &*b.boxed()));
+        });
+    }
+}
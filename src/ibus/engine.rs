@@ -1,21 +1,51 @@
 //! `ibus_engine` 表示一个输入法实现
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error;
 use std::future::Future;
 use std::marker::Send;
+use std::mem;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use pm_bin::log::info;
 use xkeysym::{KeyCode, Keysym};
 use zbus::{
-    Connection, ObjectServer, fdo, interface, object_server::SignalEmitter, zvariant::Value,
+    Connection, ObjectServer,
+    fdo::{self, Properties},
+    interface,
+    object_server::{Interface, SignalEmitter},
+    zvariant::Value,
 };
 
-use super::{IBusModifierState, LookupTable, ibus_serde::make_ibus_text};
+use super::context::{修改状态, 删除状态, 读取状态};
+use super::extension::extension_keys_from;
+use super::service::Service;
+use super::{
+    BoxedIBusEngine, EngineContext, IBusCapabilite, IBusExtensionEvent, IBusExtensionKeys,
+    IBusInputHints, IBusInputPurpose, IBusModifierState, IBusPropList, IBusPropState, IBusProperty,
+    IBusSerializable, IBusStroke, IBusText, LookupTable,
+};
 
 /// Implement this trait to implement an input method.
 ///
 /// Your implementation can use the methods of the [`IBusEngineBackend`]
 /// to display text to the user.
 pub trait IBusEngine: Send + Sync {
+    /// Converts this engine to a [`BoxedIBusEngine`], to mix different engine types in one
+    /// [`crate::ibus::IBusFactory`].
+    fn boxed(self) -> BoxedIBusEngine
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(self)
+    }
+
+    /// Receives the [`EngineContext`] of this engine, just after it is created (before any
+    /// other callback)
+    ///
+    /// Keep it to update the UI from a background task.
+    fn set_context(&mut self, _ctx: EngineContext) {}
+
     /// A key was pressed or released.
     ///
     /// `keyval` encodes the symbol of the key interpreted according to the current keyboard layout.
@@ -39,6 +69,28 @@ pub trait IBusEngine: Send + Sync {
         async { Ok(false) }
     }
 
+    /// (Handwriting) A stroke was drawn
+    ///
+    /// Collect the strokes with a [`crate::ibus::StrokeAccumulator`].
+    fn process_hand_writing_event(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _stroke: IBusStroke,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// (Handwriting) Removes the last `n_strokes` strokes, or all strokes if `n_strokes` is 0
+    fn cancel_hand_writing(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _n_strokes: u32,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
     /// 设置光标位置
     fn set_cursor_location(
         &mut self,
@@ -52,6 +104,19 @@ pub trait IBusEngine: Send + Sync {
         async { Ok(()) }
     }
 
+    /// The client (application) tells what it can display
+    ///
+    /// For example, when `caps.preedit_text()` is false, the composition should be shown with
+    /// [`IBusEngineBackend::update_auxiliary_text`] instead of the preedit text.
+    fn set_capabilities(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _caps: IBusCapabilite,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
     /// 获得焦点
     fn focus_in(
         &mut self,
@@ -70,6 +135,33 @@ pub trait IBusEngine: Send + Sync {
         async { Ok(()) }
     }
 
+    /// An input context got focus
+    ///
+    /// `object_path` identifies the input context, `client` is the name of the application
+    /// side (for example `gtk3-im:firefox` or `xim`). ibus calls this instead of
+    /// [`Self::focus_in`], the default implementation calls [`Self::focus_in`].
+    fn focus_in_id(
+        &mut self,
+        se: SignalEmitter<'_>,
+        server: &ObjectServer,
+        _object_path: String,
+        _client: String,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        self.focus_in(se, server)
+    }
+
+    /// The input context (same `object_path` as [`Self::focus_in_id`]) lost focus
+    ///
+    /// The default implementation calls [`Self::focus_out`].
+    fn focus_out_id(
+        &mut self,
+        se: SignalEmitter<'_>,
+        server: &ObjectServer,
+        _object_path: String,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        self.focus_out(se, server)
+    }
+
     /// 重置
     fn reset(
         &mut self,
@@ -97,6 +189,17 @@ pub trait IBusEngine: Send + Sync {
         async { Ok(()) }
     }
 
+    /// The engine is destroyed by ibus-daemon (`org.freedesktop.IBus.Service.Destroy`)
+    ///
+    /// This is the last callback: the engine is removed from the bus just after.
+    fn destroy(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
     /// (UI) Emitted when a candidate on a lookup table is clicked
     ///
     /// _index is the 0-based index of the clicked candiate *in the current page*, not in the full
@@ -147,6 +250,92 @@ pub trait IBusEngine: Send + Sync {
     ) -> impl Future<Output = fdo::Result<()>> + Send {
         async { Ok(()) }
     }
+
+    /// The client sends the text around the cursor
+    ///
+    /// Only called when the client has [`IBusCapabilite::surrounding_text`]. `cursor_pos` and
+    /// `anchor_pos` (the other end of the selection) count unicode characters of `text`.
+    fn set_surrounding_text(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _text: IBusText,
+        _cursor_pos: u32,
+        _anchor_pos: u32,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// The client tells the purpose of the focused text field, and hints about the input
+    ///
+    /// For example, keys should not be captured when `purpose.is_hidden()` (password or PIN).
+    fn set_content_type(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _purpose: IBusInputPurpose,
+        _hints: IBusInputHints,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// (UI) A property (registered with [`IBusEngineBackend::register_properties`]) was
+    /// clicked in the panel menu
+    ///
+    /// `state` is the new state requested for toggle and radio properties.
+    fn property_activate(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _name: String,
+        _state: IBusPropState,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// (UI) A property is shown
+    fn property_show(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _name: String,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// (UI) A property is hidden
+    fn property_hide(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _name: String,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// (UI) The panel sends an event of a panel extension (for example the emoji picker was
+    /// closed)
+    fn panel_extension_received(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _event: IBusExtensionEvent,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// (UI) The panel sends the hotkeys of the panel extensions
+    ///
+    /// Check them in [`Self::process_key_event`], and start the extension with
+    /// [`IBusEngineBackend::panel_extension`].
+    fn panel_extension_register_keys(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        _keys: IBusExtensionKeys,
+    ) -> impl Future<Output = fdo::Result<()>> + Send {
+        async { Ok(()) }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Ord, Eq)]
@@ -164,12 +353,21 @@ impl From<IBusPreeditFocusMode> for u32 {
     }
 }
 
+impl From<u32> for IBusPreeditFocusMode {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => IBusPreeditFocusMode::Commit,
+            _ => IBusPreeditFocusMode::Clear,
+        }
+    }
+}
+
 /// Methods that the IBus daemon provides for inputs methods to use
 pub trait IBusEngineBackend: IBusEngine + 'static {
     /// Type this text on behalf of the user
     fn commit_text(
         se: &SignalEmitter<'_>,
-        text: String,
+        text: impl Into<IBusText> + Send,
     ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
 
     /// (UI) Show of hide this lookup table
@@ -186,9 +384,12 @@ pub trait IBusEngineBackend: IBusEngine + 'static {
     ///
     /// cursor_pos is a value from 0 to `text.len()` indicating where the cursor should be
     /// displayed.
+    ///
+    /// Use an [`IBusText`] with attributes to style parts of the preedit text (for example
+    /// underline the converted segment).
     fn update_preedit_text(
         se: &SignalEmitter<'_>,
-        text: String,
+        text: impl Into<IBusText> + Send,
         cursor_pos: u32,
         visible: bool,
         mode: IBusPreeditFocusMode,
@@ -200,14 +401,96 @@ pub trait IBusEngineBackend: IBusEngine + 'static {
     /// to be written.
     fn update_auxiliary_text(
         se: &SignalEmitter<'_>,
-        text: String,
+        text: impl Into<IBusText> + Send,
         visible: bool,
     ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// (UI) Sets the properties (menu items) of the input method, shown in the ibus panel
+    ///
+    /// This replaces all the properties registered before. Usually called in `focus_in`.
+    fn register_properties(
+        se: &SignalEmitter<'_>,
+        props: &IBusPropList,
+    ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// (UI) Updates one property registered before (matched by `key`)
+    fn update_property(
+        se: &SignalEmitter<'_>,
+        prop: &IBusProperty,
+    ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// Sends a key event to the application, as if the user typed it
+    ///
+    /// For example to pass a (remapped) key which is not handled by the input method. Set
+    /// [`IBusModifierState::with_release`] for a key release event, or use
+    /// [`Self::forward_key_press_release`].
+    fn forward_key_event(
+        se: &SignalEmitter<'_>,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// Sends the press and then the release event of a key (with the modifiers in `state`)
+    fn forward_key_press_release(
+        se: &SignalEmitter<'_>,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// The capabilities of the client, as last received by [`IBusEngine::set_capabilities`]
+    ///
+    /// This does not lock the engine: it can be called from a callback, for example to check
+    /// `caps.preedit_text()` in [`IBusEngine::process_key_event`].
+    fn capabilities(
+        se: &SignalEmitter<'_>,
+    ) -> impl std::future::Future<Output = zbus::Result<IBusCapabilite>> + Send;
+
+    /// (UI) Starts or stops a panel extension (for example the emoji or unicode picker)
+    fn panel_extension(
+        se: &SignalEmitter<'_>,
+        event: &IBusExtensionEvent,
+    ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// Deletes `n_chars` characters of the surrounding text, starting `offset` characters from
+    /// the cursor (negative: before the cursor)
+    fn delete_surrounding_text(
+        se: &SignalEmitter<'_>,
+        offset: i32,
+        n_chars: u32,
+    ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// Asks the client to send the surrounding text (with [`IBusEngine::set_surrounding_text`])
+    ///
+    /// After the first call, the engine also reports `ActiveSurroundingText`, so ibus-daemon
+    /// sends the surrounding text by itself when it changes.
+    fn require_surrounding_text(
+        se: &SignalEmitter<'_>,
+    ) -> impl std::future::Future<Output = zbus::Result<()>> + Send;
+
+    /// The surrounding text, as last received by [`IBusEngine::set_surrounding_text`]
+    ///
+    /// Like [`Self::capabilities`], this can be called from a callback (for example to
+    /// re-convert the previous word in [`IBusEngine::process_key_event`]).
+    fn surrounding_text(
+        se: &SignalEmitter<'_>,
+    ) -> impl std::future::Future<Output = zbus::Result<IBusSurroundingText>> + Send;
+
+    /// The content type, as last received by [`IBusEngine::set_content_type`]
+    ///
+    /// Like [`Self::capabilities`], this can be called from a callback.
+    fn content_type(
+        se: &SignalEmitter<'_>,
+    ) -> impl std::future::Future<Output = zbus::Result<(IBusInputPurpose, IBusInputHints)>> + Send;
 }
 
 impl<T: IBusEngine + 'static> IBusEngineBackend for T {
-    async fn commit_text(se: &SignalEmitter<'_>, text: String) -> zbus::Result<()> {
-        Engine::<Self>::commit_text(se, make_ibus_text(text)).await
+    async fn commit_text(
+        se: &SignalEmitter<'_>,
+        text: impl Into<IBusText> + Send,
+    ) -> zbus::Result<()> {
+        Engine::<Self>::commit_text(se, text.into().serialize()).await
     }
 
     async fn update_lookup_table(
@@ -220,14 +503,14 @@ impl<T: IBusEngine + 'static> IBusEngineBackend for T {
 
     async fn update_preedit_text(
         se: &SignalEmitter<'_>,
-        text: String,
+        text: impl Into<IBusText> + Send,
         cursor_pos: u32,
         visible: bool,
         mode: IBusPreeditFocusMode,
     ) -> zbus::Result<()> {
         Engine::<Self>::update_preedit_text(
             se,
-            make_ibus_text(text),
+            text.into().serialize(),
             cursor_pos,
             visible,
             mode.into(),
@@ -237,13 +520,94 @@ impl<T: IBusEngine + 'static> IBusEngineBackend for T {
 
     async fn update_auxiliary_text(
         se: &SignalEmitter<'_>,
-        text: String,
+        text: impl Into<IBusText> + Send,
         visible: bool,
     ) -> zbus::Result<()> {
-        Engine::<Self>::update_auxiliary_text(se, make_ibus_text(text), visible).await
+        Engine::<Self>::update_auxiliary_text(se, text.into().serialize(), visible).await
+    }
+
+    async fn register_properties(se: &SignalEmitter<'_>, props: &IBusPropList) -> zbus::Result<()> {
+        Engine::<Self>::register_properties(se, props.serialize()).await
+    }
+
+    async fn update_property(se: &SignalEmitter<'_>, prop: &IBusProperty) -> zbus::Result<()> {
+        Engine::<Self>::update_property(se, prop.serialize()).await
+    }
+
+    async fn forward_key_event(
+        se: &SignalEmitter<'_>,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> zbus::Result<()> {
+        Engine::<Self>::forward_key_event(se, keyval.raw(), keycode.raw(), state.raw_value()).await
+    }
+
+    async fn forward_key_press_release(
+        se: &SignalEmitter<'_>,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> zbus::Result<()> {
+        Self::forward_key_event(se, keyval, keycode, state.with_release(false)).await?;
+        Self::forward_key_event(se, keyval, keycode, state.with_release(true)).await
+    }
+
+    async fn capabilities(se: &SignalEmitter<'_>) -> zbus::Result<IBusCapabilite> {
+        读取状态(se, |s| s.caps)
+    }
+
+    async fn panel_extension(
+        se: &SignalEmitter<'_>,
+        event: &IBusExtensionEvent,
+    ) -> zbus::Result<()> {
+        Engine::<Self>::panel_extension(se, event.serialize()).await
+    }
+
+    async fn delete_surrounding_text(
+        se: &SignalEmitter<'_>,
+        offset: i32,
+        n_chars: u32,
+    ) -> zbus::Result<()> {
+        Engine::<Self>::delete_surrounding_text(se, offset, n_chars).await
+    }
+
+    async fn require_surrounding_text(se: &SignalEmitter<'_>) -> zbus::Result<()> {
+        Engine::<Self>::require_surrounding_text(se).await?;
+        // 以后 ibus-daemon 主动发送 surrounding text (属性 `ActiveSurroundingText`)
+        let 改变 = 修改状态(se, |s| !mem::replace(&mut s.active_surrounding_text, true))?;
+        if 改变 {
+            let v = HashMap::from([("ActiveSurroundingText", Value::from(true))]);
+            Properties::properties_changed(se, Engine::<Self>::name(), v, Cow::Borrowed(&[]))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn surrounding_text(se: &SignalEmitter<'_>) -> zbus::Result<IBusSurroundingText> {
+        读取状态(se, |s| s.surrounding_text.clone())
+    }
+
+    async fn content_type(
+        se: &SignalEmitter<'_>,
+    ) -> zbus::Result<(IBusInputPurpose, IBusInputHints)> {
+        读取状态(se, |s| {
+            let (p, h) = s.content_type;
+            (p.into(), IBusInputHints::new_with_raw_value(h))
+        })
     }
 }
 
+/// The text around the cursor in the client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IBusSurroundingText {
+    pub text: IBusText,
+    /// Position of the cursor in `text` (in unicode characters)
+    pub cursor_pos: u32,
+    /// Position of the other end of the selection (same as `cursor_pos` if nothing is selected)
+    pub anchor_pos: u32,
+}
+
 /// D-Bus interface: `org.freedesktop.IBus.Engine`
 ///
 /// <https://ibus.github.io/docs/ibus-1.5/IBusEngine.html>
@@ -251,6 +615,9 @@ impl<T: IBusEngine + 'static> IBusEngineBackend for T {
 pub(crate) struct Engine<T: IBusEngine + 'static> {
     e: T,
 
+    /// caps, surrounding_text, content_type
+    ctx: EngineContext,
+
     _op: String,
 }
 
@@ -355,6 +722,12 @@ pub(crate) struct Engine<T: IBusEngine + 'static> {
 //     <signal name='PanelExtension'>
 //       <arg type='v' name='data' />
 //     </signal>
+//     <signal name='DeleteSurroundingText'>
+//       <arg type='i' name='offset_from_cursor' />
+//       <arg type='u' name='nchars' />
+//     </signal>
+//     <signal name='RequireSurroundingText'>
+//     </signal>
 //
 //     <property name='ContentType' type='(uu)' access='write' />
 //     <property name='FocusId' type='(b)' access='read' />
@@ -396,34 +769,67 @@ impl<T: IBusEngine + 'static> Engine<T> {
         self.e.set_cursor_location(se, server, x, y, w, h).await
     }
 
-    // 忽略
-    fn process_hand_writing_event(&mut self, _coordinates: Vec<f64>) -> fdo::Result<()> {
-        Ok(())
+    async fn process_hand_writing_event(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        coordinates: Vec<f64>,
+    ) -> fdo::Result<()> {
+        let stroke = IBusStroke::from_coordinates(&coordinates);
+        self.e.process_hand_writing_event(se, server, stroke).await
     }
 
-    // 忽略
-    fn cancel_hand_writing(&mut self, _n_strokes: u32) -> fdo::Result<()> {
-        Ok(())
+    async fn cancel_hand_writing(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        n_strokes: u32,
+    ) -> fdo::Result<()> {
+        self.e.cancel_hand_writing(se, server, n_strokes).await
     }
 
-    // 忽略
-    fn set_capabilities(&mut self, _caps: u32) -> fdo::Result<()> {
-        Ok(())
+    async fn set_capabilities(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        caps: u32,
+    ) -> fdo::Result<()> {
+        let caps = IBusCapabilite::new_with_raw_value(caps);
+        self.ctx.state().caps = caps;
+        self.e.set_capabilities(se, server, caps).await
     }
 
-    // 忽略 (用户界面相关)
-    fn property_activate(&mut self, _name: String, _state: u32) -> fdo::Result<()> {
-        Ok(())
+    // (UI)
+    async fn property_activate(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        name: String,
+        state: u32,
+    ) -> fdo::Result<()> {
+        self.e
+            .property_activate(se, server, name, state.into())
+            .await
     }
 
-    // 忽略 (用户界面相关)
-    fn property_show(&mut self, _name: String) -> fdo::Result<()> {
-        Ok(())
+    // (UI)
+    async fn property_show(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        name: String,
+    ) -> fdo::Result<()> {
+        self.e.property_show(se, server, name).await
     }
 
-    // 忽略 (用户界面相关)
-    fn property_hide(&mut self, _name: String) -> fdo::Result<()> {
-        Ok(())
+    // (UI)
+    async fn property_hide(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        name: String,
+    ) -> fdo::Result<()> {
+        self.e.property_hide(se, server, name).await
     }
 
     // (UI)
@@ -448,9 +854,14 @@ impl<T: IBusEngine + 'static> Engine<T> {
         self.e.focus_in(se, server).await
     }
 
-    fn focus_in_id(&mut self, _object_path: String, _client: String) -> fdo::Result<()> {
-        // TODO
-        Ok(())
+    async fn focus_in_id(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        object_path: String,
+        client: String,
+    ) -> fdo::Result<()> {
+        self.e.focus_in_id(se, server, object_path, client).await
     }
 
     async fn focus_out(
@@ -461,9 +872,13 @@ impl<T: IBusEngine + 'static> Engine<T> {
         self.e.focus_out(se, server).await
     }
 
-    fn focus_out_id(&mut self, _object_path: String) -> fdo::Result<()> {
-        // TODO
-        Ok(())
+    async fn focus_out_id(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        object_path: String,
+    ) -> fdo::Result<()> {
+        self.e.focus_out_id(se, server, object_path).await
     }
 
     async fn reset(
@@ -526,24 +941,45 @@ impl<T: IBusEngine + 'static> Engine<T> {
         self.e.cursor_down(se, server).await
     }
 
-    // 忽略
-    fn set_surrounding_text(
+    async fn set_surrounding_text(
         &mut self,
-        _text: Value,
-        _cursor_pos: u32,
-        _anchor_pos: u32,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        text: Value<'_>,
+        cursor_pos: u32,
+        anchor_pos: u32,
     ) -> fdo::Result<()> {
-        Ok(())
+        let text = IBusText::try_from(&text)?;
+        self.ctx.state().surrounding_text = IBusSurroundingText {
+            text: text.clone(),
+            cursor_pos,
+            anchor_pos,
+        };
+        self.e
+            .set_surrounding_text(se, server, text, cursor_pos, anchor_pos)
+            .await
     }
 
-    // 忽略 (用户界面相关)
-    fn panel_extension_received(&mut self, _event: Value) -> fdo::Result<()> {
-        Ok(())
+    // (UI)
+    async fn panel_extension_received(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        event: Value<'_>,
+    ) -> fdo::Result<()> {
+        let event = IBusExtensionEvent::try_from(&event)?;
+        self.e.panel_extension_received(se, server, event).await
     }
 
-    // 忽略 (用户界面相关)
-    fn panel_extension_register_keys(&mut self, _data: Value) -> fdo::Result<()> {
-        Ok(())
+    // (UI)
+    async fn panel_extension_register_keys(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        data: Value<'_>,
+    ) -> fdo::Result<()> {
+        let keys = extension_keys_from(&data)?;
+        self.e.panel_extension_register_keys(se, server, keys).await
     }
 
     #[zbus(signal)]
@@ -575,66 +1011,129 @@ impl<T: IBusEngine + 'static> Engine<T> {
         visible: bool,
     ) -> zbus::Result<()>;
 
-    // 忽略 (用户界面相关)
+    // (UI)
     #[zbus(signal)]
     async fn register_properties(se: &SignalEmitter<'_>, props: Value<'_>) -> zbus::Result<()>;
 
-    // 忽略 (用户界面相关)
+    // (UI)
     #[zbus(signal)]
     async fn update_property(se: &SignalEmitter<'_>, prop: Value<'_>) -> zbus::Result<()>;
 
     #[zbus(signal)]
-    pub async fn forward_key_event(
+    async fn forward_key_event(
         se: &SignalEmitter<'_>,
         keyval: u32,
         keycode: u32,
         state: u32,
     ) -> zbus::Result<()>;
 
-    // 忽略 (用户界面相关)
+    // (UI)
     #[zbus(signal)]
     async fn panel_extension(se: &SignalEmitter<'_>, data: Value<'_>) -> zbus::Result<()>;
 
+    #[zbus(signal)]
+    async fn delete_surrounding_text(
+        se: &SignalEmitter<'_>,
+        offset_from_cursor: i32,
+        nchars: u32,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn require_surrounding_text(se: &SignalEmitter<'_>) -> zbus::Result<()>;
+
     #[zbus(property)]
     fn content_type(&self) -> (u32, u32) {
-        // TODO
-        (0, 0)
+        self.ctx.state().content_type
     }
 
     #[zbus(property)]
-    fn set_content_type(&mut self, _t: (u32, u32)) -> fdo::Result<()> {
-        // TODO
-        Ok(())
+    async fn set_content_type(
+        &mut self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+        t: (u32, u32),
+    ) -> fdo::Result<()> {
+        self.ctx.state().content_type = t;
+        self.e
+            .set_content_type(
+                se,
+                server,
+                t.0.into(),
+                IBusInputHints::new_with_raw_value(t.1),
+            )
+            .await
     }
 
     #[zbus(property)]
     fn focus_id(&self) -> bool {
-        // TODO
-        false
+        // 使用 FocusInId 和 FocusOutId
+        true
     }
 
     #[zbus(property)]
     fn active_surrounding_text(&self) -> bool {
-        // TODO
-        false
+        self.ctx.state().active_surrounding_text
     }
 }
 
+/// engine 编号 (每个进程从 1 开始递增)
+///
+/// 源文件: `ibus/src/ibusfactory.c`
+/// 变量: `static guint engine_id`
+static ENGINE_ID: AtomicU32 = AtomicU32::new(0);
+
+/// engine 的 object path
+pub(crate) fn engine_path(id: u32) -> String {
+    format!("/org/freedesktop/IBus/Engine/{}", id)
+}
+
 impl<T: IBusEngine + 'static> Engine<T> {
     /// create engine (include ibus init)
-    pub async fn new(c: &Connection, e: T) -> Result<String, Box<dyn Error>> {
+    ///
+    /// Every engine gets its own object path, and implements `org.freedesktop.IBus.Service` to
+    /// be destroyed. Returns the context of the new engine (also given to
+    /// `IBusEngine::set_context()`).
+    pub async fn register(c: &Connection, mut e: T) -> Result<EngineContext, Box<dyn Error>> {
         // 源文件: `ibus/src/ibusfactory.c`
         // 函数: `ibus_factory_real_create_engine()`
-        let object_path = format!("/org/freedesktop/IBus/Engine/{}", 1);
+        let id = ENGINE_ID.fetch_add(1, Ordering::SeqCst) + 1;
+        let object_path = engine_path(id);
 
+        let ctx = EngineContext::new(c, object_path.clone())?;
+        e.set_context(ctx.clone());
         let o = Engine {
             e,
+            ctx: ctx.clone(),
             _op: object_path.clone(),
         };
 
         c.object_server().at(object_path.clone(), o).await?;
+        let r = c
+            .object_server()
+            .at(object_path.clone(), Service::<T>::new())
+            .await;
+        if let Err(e) = r {
+            // 不能销毁的 engine: 删除
+            删除状态(c, &object_path);
+            let _ = c
+                .object_server()
+                .remove::<Engine<T>, _>(object_path.as_str())
+                .await;
+            return Err(e.into());
+        }
 
         info!("创建 engine 成功: {}", object_path);
-        Ok(object_path)
+        Ok(ctx)
+    }
+
+    /// 调用 `IBusEngine::destroy()`, 然后 `EngineContext` 失效
+    pub(crate) async fn destroy(
+        &mut self,
+        se: SignalEmitter<'_>,
+        server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        let r = self.e.destroy(se, server).await;
+        self.ctx.state().destroyed = true;
+        r
     }
 }
@@ -22,3 +22,15 @@ impl Display for IBusErr {
         write!(f, "{:?}", self)
     }
 }
+
+impl From<IBusErr> for zbus::Error {
+    fn from(value: IBusErr) -> Self {
+        zbus::Error::Failure(value.msg)
+    }
+}
+
+impl From<IBusErr> for zbus::fdo::Error {
+    fn from(value: IBusErr) -> Self {
+        zbus::fdo::Error::InvalidArgs(value.msg)
+    }
+}
@@ -0,0 +1,94 @@
+//! 执行器 (executor): 后台任务, 不依赖具体的异步运行时
+//!
+//! + feature `tokio`: 使用 tokio
+//! + feature `async-io`: 使用全局的 `async-executor` (一个线程), 或者 [`set_spawner`] (例如 smol)
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+use super::IBusErr;
+
+/// A background task, see [`set_spawner`]
+pub type BoxedTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+type Spawner = Box<dyn Fn(BoxedTask) + Send + Sync>;
+
+static SPAWNER: OnceLock<Spawner> = OnceLock::new();
+
+/// Sets the function which starts the background tasks of librush (for example on smol)
+///
+/// This can only be set once, before the first task is started. By default, tasks are started
+/// with `tokio::spawn` (feature `tokio`), or on a global executor thread (feature `async-io`).
+///
+/// ```ignore
+/// librush::ibus::set_spawner(|t| smol::spawn(t).detach()).unwrap();
+/// ```
+pub fn set_spawner(f: impl Fn(BoxedTask) + Send + Sync + 'static) -> Result<(), IBusErr> {
+    SPAWNER
+        .set(Box::new(f))
+        .map_err(|_| IBusErr::new("spawner is already set".to_string()))
+}
+
+/// Starts a background task (with the function from [`set_spawner`])
+pub fn spawn(f: impl Future<Output = ()> + Send + 'static) {
+    SPAWNER.get_or_init(|| Box::new(默认))(Box::pin(f))
+}
+
+#[cfg(feature = "tokio")]
+fn 默认(t: BoxedTask) {
+    tokio::spawn(t);
+}
+
+#[cfg(not(feature = "tokio"))]
+fn 默认(t: BoxedTask) {
+    use async_executor::Executor;
+    use std::sync::Once;
+
+    static E: Executor<'static> = Executor::new();
+    static 启动: Once = Once::new();
+
+    启动.call_once(|| {
+        std::thread::Builder::new()
+            .name("librush".to_string())
+            .spawn(|| async_io::block_on(E.run(futures_lite::future::pending::<()>())))
+            .expect("failed to start executor thread");
+    });
+    E.spawn(t).detach();
+}
+
+/// Runs a future to completion (in `main`, or in tests)
+///
+/// Uses one global tokio runtime (feature `tokio`, created by the first call and kept for the
+/// background tasks), otherwise `async_io::block_on`.
+///
+/// It blocks the current thread: do not call it from async code, for example from an
+/// [`crate::ibus::IBusEngine`] callback (with tokio this panics).
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    #[cfg(feature = "tokio")]
+    {
+        static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+        RUNTIME
+            .get_or_init(|| tokio::runtime::Runtime::new().expect("failed to create tokio runtime"))
+            .block_on(f)
+    }
+    #[cfg(not(feature = "tokio"))]
+    {
+        async_io::block_on(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn executor_spawn() {
+        block_on(async {
+            let (tx, rx) = async_channel::bounded(1);
+            spawn(async move {
+                tx.send(1).await.unwrap();
+            });
+            assert_eq!(rx.recv().await.unwrap(), 1);
+        });
+    }
+}
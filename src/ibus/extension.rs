@@ -0,0 +1,162 @@
+//! 面板扩展 (panel extension): 例如 emoji 和 unicode 选择器
+use std::collections::HashMap;
+
+use xkeysym::{KeyCode, Keysym};
+use zbus::zvariant::Value;
+
+use super::ibus_serde::{ibus_field, ibus_try_from, unwrap_variant};
+use super::{IBusAttachments, IBusErr, IBusModifierState, IBusSerializable};
+
+// 源文件: `ibus/src/ibusxevent.c`
+// 函数: `ibus_extension_event_serialize()`
+//
+// (sa{sv}usbbs)
+const IBUS_EXTENSION_EVENT_VERSION: u32 = 1;
+
+/// `IBusExtensionEvent`: starts or stops a panel extension (for example the emoji picker)
+///
+/// <https://ibus.github.io/docs/ibus-1.5/IBusExtensionEvent.html>
+///
+/// ```
+/// use librush::ibus::IBusExtensionEvent;
+///
+/// // emit it with `IBusEngineBackend::panel_extension()`
+/// let e = IBusExtensionEvent::new("emoji", true);
+/// assert!(e.is_enabled);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IBusExtensionEvent {
+    /// Name of the extension: `emoji` or `unicode`
+    pub name: String,
+    pub is_enabled: bool,
+    /// True if the event is sent from the extension itself
+    pub is_extension: bool,
+    /// Parameters of the extension (for example `category-list` for `unicode`)
+    pub params: String,
+}
+
+impl IBusExtensionEvent {
+    pub fn new(name: impl Into<String>, is_enabled: bool) -> Self {
+        Self {
+            name: name.into(),
+            is_enabled,
+            ..Default::default()
+        }
+    }
+}
+
+impl IBusSerializable for IBusExtensionEvent {
+    const NAME: &'static str = "IBusExtensionEvent";
+    const MIN_FIELDS: usize = 5;
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        vec![
+            Value::new(IBUS_EXTENSION_EVENT_VERSION),
+            Value::new(self.name.clone()),
+            Value::new(self.is_enabled),
+            Value::new(self.is_extension),
+            Value::new(self.params.clone()),
+        ]
+    }
+
+    fn from_fields(f: &[Value<'_>], _attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        Ok(Self {
+            name: ibus_field(f, 1)?,
+            is_enabled: ibus_field(f, 2)?,
+            is_extension: ibus_field(f, 3)?,
+            params: ibus_field(f, 4)?,
+        })
+    }
+}
+
+ibus_try_from!(IBusExtensionEvent);
+
+/// `IBusProcessKeyEventData`: a key (hotkey) of a panel extension
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IBusProcessKeyEventData {
+    pub keyval: Keysym,
+    pub keycode: KeyCode,
+    pub state: IBusModifierState,
+}
+
+/// Hotkeys of each panel extension (by name, for example `emoji`)
+pub type IBusExtensionKeys = HashMap<String, Vec<IBusProcessKeyEventData>>;
+
+// 源文件: `ibus/src/ibusengine.c`
+// 函数: `ibus_engine_service_panel_extension_register_keys()`
+//
+// a{sv}: 名称 -> a(uuu)
+pub(crate) fn extension_keys_from(v: &Value<'_>) -> Result<IBusExtensionKeys, IBusErr> {
+    let e = || {
+        IBusErr::new(format!(
+            "expected extension keys a{{sv}}, got signature: {}",
+            v.value_signature()
+        ))
+    };
+    let Value::Dict(d) = unwrap_variant(v) else {
+        return Err(e());
+    };
+    let mut o = IBusExtensionKeys::new();
+    for (k, v) in d.iter() {
+        let Value::Str(k) = k else {
+            return Err(e());
+        };
+        let keys: Vec<(u32, u32, u32)> = unwrap_variant(v)
+            .try_clone()
+            .and_then(Vec::try_from)
+            .map_err(|x| IBusErr::new(format!("extension keys {}: {}", k, x)))?;
+        let keys = keys
+            .into_iter()
+            .map(|(keyval, keycode, state)| IBusProcessKeyEventData {
+                keyval: keyval.into(),
+                keycode: keycode.into(),
+                state: IBusModifierState::new_with_raw_value(state),
+            })
+            .collect();
+        o.insert(k.to_string(), keys);
+    }
+    Ok(o)
+}
+
+/// 编码 (用于测试)
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn extension_keys_to(keys: &IBusExtensionKeys) -> Value<'static> {
+    let d: HashMap<String, Value<'static>> = keys
+        .iter()
+        .map(|(k, v)| {
+            let v: Vec<(u32, u32, u32)> = v
+                .iter()
+                .map(|i| (i.keyval.raw(), i.keycode.raw(), i.state.raw_value()))
+                .collect();
+            (k.clone(), Value::new(v))
+        })
+        .collect();
+    Value::new(d)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ibus_extension_event_zvariant() {
+        let mut e = IBusExtensionEvent::new("unicode", true);
+        e.params = "category-list".to_string();
+        let v = e.serialize();
+        assert_eq!(v.value_signature(), "(sa{sv}usbbs)");
+        assert_eq!(IBusExtensionEvent::try_from(&v).unwrap(), e);
+    }
+
+    #[test]
+    fn ibus_extension_keys() {
+        let k = IBusProcessKeyEventData {
+            keyval: Keysym::period,
+            keycode: KeyCode::new(52),
+            state: IBusModifierState::new_with_raw_value(0).with_control(true),
+        };
+        let keys = IBusExtensionKeys::from([("emoji".to_string(), vec![k])]);
+        let v = extension_keys_to(&keys);
+        assert_eq!(unwrap_variant(&v).value_signature(), "a{sv}");
+        assert_eq!(extension_keys_from(&v).unwrap(), keys);
+    }
+}
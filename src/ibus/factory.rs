@@ -1,5 +1,6 @@
 //! factory: 用于创建 engine
 use std::error::Error;
+use std::future::Future;
 use std::marker::PhantomData;
 
 use pm_bin::log::debug;
@@ -9,27 +10,26 @@ use super::IBusEngine;
 use super::engine::Engine;
 
 /// Implement this trait to create engine
+///
+/// Use [`crate::ibus::BoxedIBusEngine`] as `T` to create engines of different types (by `name`).
 pub trait IBusFactory<T: IBusEngine>: Send + Sync {
     /// create the engine (input method)
-    fn create_engine(&mut self, name: String) -> Result<T, String>;
+    ///
+    /// `name` is the engine name in the component description. This can do I/O (for example
+    /// load a dictionary) before returning.
+    fn create_engine(&mut self, name: String) -> impl Future<Output = Result<T, String>> + Send;
 }
 
 #[derive(Debug, Clone)]
 pub struct Factory<T: IBusEngine, U: IBusFactory<T>> {
     _t: PhantomData<T>,
 
-    c: Connection,
-
     f: U,
 }
 
 impl<T: IBusEngine, U: IBusFactory<T>> Factory<T, U> {
-    pub fn new(c: Connection, f: U) -> Self {
-        Self {
-            _t: PhantomData,
-            c,
-            f,
-        }
+    pub fn new(f: U) -> Self {
+        Self { _t: PhantomData, f }
     }
 }
 
@@ -44,24 +44,28 @@ impl<T: IBusEngine, U: IBusFactory<T>> Factory<T, U> {
 #[interface(name = "org.freedesktop.IBus.Factory")]
 impl<T: IBusEngine + 'static, U: IBusFactory<T> + 'static> Factory<T, U> {
     #[zbus(name = "CreateEngine")]
-    async fn create_engine(&mut self, name: String) -> fdo::Result<ObjectPath<'_>> {
+    async fn create_engine(
+        &mut self,
+        #[zbus(connection)] c: &Connection,
+        name: String,
+    ) -> fdo::Result<ObjectPath<'_>> {
         debug!("CreateEngine");
 
         let e = self
             .f
             .create_engine(name.clone())
+            .await
             .map_err(|s| fdo::Error::Failed(s))?;
 
-        let p = Engine::new(&self.c, e)
+        let ctx = Engine::register(c, e)
             .await
             .map_err(|e| fdo::Error::Failed(format!("{:?}", e)))?;
-        let o = ObjectPath::try_from(p).map_err(|e| fdo::Error::Failed(format!("{:?}", e)))?;
-        Ok(o)
+        Ok(ctx.path().clone())
     }
 }
 
 // `ibus/src/ibusshare.h`
-const IBUS_PATH_FACTORY: &'static str = "/org/freedesktop/IBus/Factory";
+pub(crate) const IBUS_PATH_FACTORY: &'static str = "/org/freedesktop/IBus/Factory";
 
 /// ibus 初始化: 注册 engine factory
 ///
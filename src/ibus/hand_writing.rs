@@ -0,0 +1,116 @@
+//! 手写输入: 笔画
+//!
+//! 每次 `ProcessHandWritingEvent` 发送一个笔画: `[x1, y1, x2, y2, ..]`, 坐标范围 0.0 ~ 1.0
+
+/// A point of a stroke, coordinates are from 0.0 to 1.0
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct IBusPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A stroke of handwriting input (from pen down to pen up)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IBusStroke {
+    pub points: Vec<IBusPoint>,
+}
+
+impl IBusStroke {
+    /// Groups `[x1, y1, x2, y2, ..]` into points (a trailing odd value is ignored)
+    pub fn from_coordinates(coordinates: &[f64]) -> Self {
+        let points = coordinates
+            .chunks_exact(2)
+            .map(|p| IBusPoint { x: p[0], y: p[1] })
+            .collect();
+        Self { points }
+    }
+
+    /// `[x1, y1, x2, y2, ..]`
+    pub fn coordinates(&self) -> Vec<f64> {
+        self.points.iter().flat_map(|p| [p.x, p.y]).collect()
+    }
+}
+
+/// The strokes of the character being written
+///
+/// ```
+/// use librush::ibus::{IBusStroke, StrokeAccumulator};
+///
+/// let mut s = StrokeAccumulator::new();
+/// s.push(IBusStroke::from_coordinates(&[0.1, 0.1, 0.9, 0.1]));
+/// s.push(IBusStroke::from_coordinates(&[0.5, 0.0, 0.5, 1.0]));
+/// s.cancel(1);
+/// assert_eq!(s.strokes().len(), 1);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StrokeAccumulator {
+    strokes: Vec<IBusStroke>,
+}
+
+impl StrokeAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stroke (empty strokes are ignored)
+    pub fn push(&mut self, stroke: IBusStroke) {
+        if !stroke.points.is_empty() {
+            self.strokes.push(stroke);
+        }
+    }
+
+    /// Removes the last `n_strokes` strokes, or all strokes if `n_strokes` is 0
+    /// (same as `CancelHandWriting`)
+    pub fn cancel(&mut self, n_strokes: u32) {
+        let n = n_strokes as usize;
+        if n == 0 || n >= self.strokes.len() {
+            self.strokes.clear();
+        } else {
+            self.strokes.truncate(self.strokes.len() - n);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.strokes.clear();
+    }
+
+    pub fn strokes(&self) -> &[IBusStroke] {
+        &self.strokes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strokes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ibus_stroke_coordinates() {
+        let s = IBusStroke::from_coordinates(&[0.1, 0.2, 0.3, 0.4, 0.5]);
+        assert_eq!(
+            s.points,
+            [IBusPoint { x: 0.1, y: 0.2 }, IBusPoint { x: 0.3, y: 0.4 }]
+        );
+        assert_eq!(s.coordinates(), [0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn stroke_accumulator_cancel() {
+        let mut s = StrokeAccumulator::new();
+        for i in 0..4 {
+            s.push(IBusStroke::from_coordinates(&[i as f64, 0.0]));
+        }
+        s.push(IBusStroke::default());
+        assert_eq!(s.strokes().len(), 4);
+        s.cancel(1);
+        assert_eq!(s.strokes().last().unwrap().points[0].x, 2.0);
+        s.cancel(5);
+        assert!(s.is_empty());
+        s.push(IBusStroke::from_coordinates(&[0.0, 0.0]));
+        s.cancel(0);
+        assert!(s.is_empty());
+    }
+}
@@ -1,8 +1,203 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
-use arbitrary_int::u11;
+use arbitrary_int::{u11, u20, u24};
 use bitbybit::bitfield;
-use zbus::zvariant::{Structure, Value};
+use zbus::zvariant::{self, StructureBuilder, Value};
+
+use super::IBusErr;
+
+/// 实现 `TryFrom<&Value>` (使用 [`IBusSerializable::deserialize`])
+macro_rules! ibus_try_from {
+    ($($t:ty),*) => {$(
+        impl TryFrom<&::zbus::zvariant::Value<'_>> for $t {
+            type Error = $crate::ibus::IBusErr;
+
+            fn try_from(value: &::zbus::zvariant::Value<'_>) -> Result<Self, Self::Error> {
+                <$t as $crate::ibus::IBusSerializable>::deserialize(value)
+            }
+        }
+    )*};
+}
+pub(crate) use ibus_try_from;
+
+// 源文件: `ibus/src/ibusattribute.h`
+
+/// `IBusAttrUnderline`: the style of an underline attribute
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IBusAttrUnderline {
+    /// `IBUS_ATTR_UNDERLINE_NONE`: no underline.
+    None,
+    /// `IBUS_ATTR_UNDERLINE_SINGLE`: single underline.
+    Single,
+    /// `IBUS_ATTR_UNDERLINE_DOUBLE`: double underline.
+    Double,
+    /// `IBUS_ATTR_UNDERLINE_LOW`: low underline.
+    Low,
+    /// `IBUS_ATTR_UNDERLINE_ERROR`: underline for errors (usually a red wave).
+    Error,
+}
+
+impl From<u32> for IBusAttrUnderline {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => IBusAttrUnderline::Single,
+            2 => IBusAttrUnderline::Double,
+            3 => IBusAttrUnderline::Low,
+            4 => IBusAttrUnderline::Error,
+            _ => IBusAttrUnderline::None,
+        }
+    }
+}
+
+impl From<IBusAttrUnderline> for u32 {
+    fn from(value: IBusAttrUnderline) -> Self {
+        match value {
+            IBusAttrUnderline::None => 0,
+            IBusAttrUnderline::Single => 1,
+            IBusAttrUnderline::Double => 2,
+            IBusAttrUnderline::Low => 3,
+            IBusAttrUnderline::Error => 4,
+        }
+    }
+}
+
+/// The kind (and value) of an [`IBusAttribute`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IBusAttr {
+    /// `IBUS_ATTR_TYPE_UNDERLINE`
+    Underline(IBusAttrUnderline),
+    /// `IBUS_ATTR_TYPE_FOREGROUND`: text color, as `0xRRGGBB`
+    Foreground(u32),
+    /// `IBUS_ATTR_TYPE_BACKGROUND`: background color, as `0xRRGGBB`
+    Background(u32),
+    /// `IBUS_ATTR_TYPE_HINT`: semantic hint (`IBusAttrPreedit`), the panel chooses the style
+    Hint(u32),
+}
+
+impl IBusAttr {
+    /// `(type, value)` as ibus sees it
+    fn raw(self) -> (u32, u32) {
+        match self {
+            IBusAttr::Underline(u) => (1, u.into()),
+            IBusAttr::Foreground(c) => (2, c),
+            IBusAttr::Background(c) => (3, c),
+            IBusAttr::Hint(h) => (4, h),
+        }
+    }
+
+    fn from_raw(t: u32, v: u32) -> Result<Self, IBusErr> {
+        match t {
+            1 => Ok(IBusAttr::Underline(v.into())),
+            2 => Ok(IBusAttr::Foreground(v)),
+            3 => Ok(IBusAttr::Background(v)),
+            4 => Ok(IBusAttr::Hint(v)),
+            _ => Err(IBusErr::new(format!("unknown IBusAttribute type: {}", t))),
+        }
+    }
+}
+
+/// `IBusAttribute`: a style applied to a range of characters of an [`IBusText`]
+///
+/// `start_index` and `end_index` count unicode characters (not bytes), `end_index` is exclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IBusAttribute {
+    pub attr: IBusAttr,
+    pub start_index: u32,
+    pub end_index: u32,
+}
+
+impl IBusAttribute {
+    pub fn new(attr: IBusAttr, range: Range<u32>) -> Self {
+        Self {
+            attr,
+            start_index: range.start,
+            end_index: range.end,
+        }
+    }
+
+    pub fn underline(range: Range<u32>, underline: IBusAttrUnderline) -> Self {
+        Self::new(IBusAttr::Underline(underline), range)
+    }
+
+    pub fn foreground(range: Range<u32>, color: u32) -> Self {
+        Self::new(IBusAttr::Foreground(color), range)
+    }
+
+    pub fn background(range: Range<u32>, color: u32) -> Self {
+        Self::new(IBusAttr::Background(color), range)
+    }
+}
+
+// IBusAttribute
+//
+// (
+//   'IBusAttribute',
+//   @a{sv} {},
+//   uint32 1,  // type
+//   uint32 1,  // value
+//   uint32 0,  // start_index
+//   uint32 2   // end_index
+// )
+impl IBusSerializable for IBusAttribute {
+    const NAME: &'static str = "IBusAttribute";
+    const MIN_FIELDS: usize = 4;
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        let (t, v) = self.attr.raw();
+        vec![
+            Value::new(t),
+            Value::new(v),
+            Value::new(self.start_index),
+            Value::new(self.end_index),
+        ]
+    }
+
+    fn from_fields(f: &[Value<'_>], _attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        let attr = IBusAttr::from_raw(ibus_field(f, 0)?, ibus_field(f, 1)?)?;
+        Ok(Self {
+            attr,
+            start_index: ibus_field(f, 2)?,
+            end_index: ibus_field(f, 3)?,
+        })
+    }
+}
+
+/// `IBusAttrList`: all the attributes of an [`IBusText`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IBusAttrList {
+    pub attributes: Vec<IBusAttribute>,
+}
+
+impl IBusAttrList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn append(&mut self, attr: IBusAttribute) {
+        self.attributes.push(attr);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+}
+
+// (sa{sv}av)
+impl IBusSerializable for IBusAttrList {
+    const NAME: &'static str = "IBusAttrList";
+    const MIN_FIELDS: usize = 1;
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        vec![ibus_array_from(&self.attributes)]
+    }
+
+    fn from_fields(f: &[Value<'_>], _attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        Ok(Self {
+            attributes: ibus_array_of(&f[0])?,
+        })
+    }
+}
 
 // 源文件: `ibus/src/ibustext.c`
 // IBusText
@@ -31,30 +226,292 @@ use zbus::zvariant::{Structure, Value};
 //   )>,
 // )
 
-/// `IBusText`: serialize data as ibus format
-pub fn make_ibus_text(text: String) -> Value<'static> {
-    // 构造内部的 variant  struct
-    // (sa{sv}av)
-    let st1 = Structure::from((
-        "IBusAttrList",
-        HashMap::<String, Value<'static>>::new(),
-        Vec::<Value<'static>>::new(),
-    ));
+/// `IBusText`: a text with (optional) style attributes
+///
+/// ```
+/// use librush::ibus::{IBusAttrUnderline, IBusText};
+///
+/// // "converted segment" + "raw segment"
+/// let t = IBusText::new("你好ma")
+///     .underline(0..2, IBusAttrUnderline::Single)
+///     .foreground(2..4, 0x808080);
+/// assert_eq!(t.attrs.attributes.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IBusText {
+    pub text: String,
+    pub attrs: IBusAttrList,
+    /// Extra data for a custom panel (for example the source of a candidate)
+    pub attachments: IBusAttachments,
+}
+
+impl IBusText {
+    /// Creates a text without any attribute
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            attrs: IBusAttrList::new(),
+            attachments: IBusAttachments::new(),
+        }
+    }
+
+    /// Adds an attribute
+    pub fn attribute(mut self, attr: IBusAttribute) -> Self {
+        self.attrs.append(attr);
+        self
+    }
+
+    /// Underlines the characters in `range`
+    pub fn underline(self, range: Range<u32>, underline: IBusAttrUnderline) -> Self {
+        self.attribute(IBusAttribute::underline(range, underline))
+    }
+
+    /// Sets the text color (`0xRRGGBB`) of the characters in `range`
+    pub fn foreground(self, range: Range<u32>, color: u32) -> Self {
+        self.attribute(IBusAttribute::foreground(range, color))
+    }
+
+    /// Sets the background color (`0xRRGGBB`) of the characters in `range`
+    pub fn background(self, range: Range<u32>, color: u32) -> Self {
+        self.attribute(IBusAttribute::background(range, color))
+    }
+
+    /// Adds an attachment
+    pub fn attachment(mut self, key: impl Into<String>, value: impl Into<Value<'static>>) -> Self {
+        self.attachments.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// The number of unicode characters (the unit of attribute ranges)
+    pub fn char_len(&self) -> u32 {
+        self.text.chars().count() as u32
+    }
+}
+
+// (sa{sv}sv)
+impl IBusSerializable for IBusText {
+    const NAME: &'static str = "IBusText";
+    const MIN_FIELDS: usize = 2;
+
+    fn attachments(&self) -> Option<&IBusAttachments> {
+        Some(&self.attachments)
+    }
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        vec![
+            Value::new(self.text.clone()),
+            Value::new(self.attrs.serialize()),
+        ]
+    }
+
+    fn from_fields(f: &[Value<'_>], attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        Ok(Self {
+            text: ibus_field(f, 0)?,
+            attrs: IBusAttrList::deserialize(&f[1])?,
+            attachments,
+        })
+    }
+}
+
+ibus_try_from!(IBusAttribute, IBusAttrList, IBusText);
+
+impl From<String> for IBusText {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for IBusText {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl PartialEq<str> for IBusText {
+    fn eq(&self, other: &str) -> bool {
+        self.text == other
+    }
+}
+
+impl PartialEq<&str> for IBusText {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+impl PartialEq<String> for IBusText {
+    fn eq(&self, other: &String) -> bool {
+        &self.text == other
+    }
+}
+
+/// `a{sv}`: extra data attached to an IBus object
+///
+/// ibus passes it through unchanged, so a custom panel can read it.
+pub type IBusAttachments = HashMap<String, Value<'static>>;
+
+/// An IBus object (`IBusSerializable` in ibus): a struct `(sa{sv}..)` with the type name, the
+/// attachments and then the fields of the type
+///
+/// Implement [`Self::fields`] and [`Self::from_fields`], the header is handled by
+/// [`Self::serialize`] and [`Self::deserialize`].
+pub trait IBusSerializable: Sized {
+    /// Type name, for example `IBusText`
+    const NAME: &'static str;
+    /// Minimum number of fields when decoding (older versions of ibus may send fewer fields)
+    const MIN_FIELDS: usize;
+
+    /// `None` if this type does not keep attachments (they are dropped when decoding)
+    fn attachments(&self) -> Option<&IBusAttachments> {
+        None
+    }
+
+    /// The fields after the header, as `Value::new(x)` (nested objects become variants `v`)
+    fn fields(&self) -> Vec<Value<'static>>;
+
+    /// Decodes from the fields after the header (at least [`Self::MIN_FIELDS`])
+    fn from_fields(f: &[Value<'_>], attachments: IBusAttachments) -> Result<Self, IBusErr>;
+
+    fn serialize(&self) -> Value<'static> {
+        let a = self.attachments().cloned().unwrap_or_default();
+        let mut b = StructureBuilder::new().add_field(Self::NAME).add_field(a);
+        for f in self.fields() {
+            b = b.append_field(f);
+        }
+        // 至少有 2 个字段, 不会失败
+        Value::new(b.build().unwrap())
+    }
+
+    fn deserialize(v: &Value<'_>) -> Result<Self, IBusErr> {
+        let (a, f) = ibus_object(v, Self::NAME, Self::MIN_FIELDS)?;
+        Self::from_fields(f, ibus_attachments(a)?)
+    }
+}
+
+// 反序列化 (`Value` -> rust)
+//
+// 每个 IBus 对象都是一个 struct: (名称, 附件 a{sv}, 字段 ..)
+
+/// 去掉外层的 variant
+pub(crate) fn unwrap_variant<'a, 'b>(mut v: &'a Value<'b>) -> &'a Value<'b> {
+    while let Value::Value(i) = v {
+        v = i;
+    }
+    v
+}
+
+/// 检查 IBus 对象的名称, 返回 附件 和 (名称 和 附件 之后的) 字段
+///
+/// `n`: 最少的字段个数
+fn ibus_object<'a, 'b>(
+    v: &'a Value<'b>,
+    name: &str,
+    n: usize,
+) -> Result<(&'a Value<'b>, &'a [Value<'b>]), IBusErr> {
+    let e = || {
+        IBusErr::new(format!(
+            "expected {} (sa{{sv}}..), got signature: {}",
+            name,
+            v.value_signature()
+        ))
+    };
+    let Value::Structure(st) = unwrap_variant(v) else {
+        return Err(e());
+    };
+    let f = st.fields();
+    match f.first() {
+        Some(Value::Str(s)) if s.as_str() == name => {}
+        Some(Value::Str(s)) => {
+            return Err(IBusErr::new(format!(
+                "expected {}, got {} (signature: {})",
+                name,
+                s.as_str(),
+                v.value_signature()
+            )));
+        }
+        _ => return Err(e()),
+    }
+    if f.len() < n + 2 {
+        return Err(IBusErr::new(format!(
+            "expected {} with at least {} fields, got signature: {}",
+            name,
+            n,
+            v.value_signature()
+        )));
+    }
+    Ok((&f[1], &f[2..]))
+}
+
+/// 附件 `a{sv}`
+fn ibus_attachments(v: &Value<'_>) -> Result<IBusAttachments, IBusErr> {
+    let e = || {
+        IBusErr::new(format!(
+            "expected attachments a{{sv}}, got signature: {}",
+            v.value_signature()
+        ))
+    };
+    let Value::Dict(d) = unwrap_variant(v) else {
+        return Err(e());
+    };
+    let mut o = IBusAttachments::new();
+    for (k, v) in d.iter() {
+        let Value::Str(k) = k else {
+            return Err(e());
+        };
+        let v = unwrap_variant(v)
+            .try_to_owned()
+            .map_err(|x| IBusErr::new(format!("attachment {}: {}", k, x)))?;
+        o.insert(k.to_string(), v.into());
+    }
+    Ok(o)
+}
+
+/// 获取第 `i` 个字段的值
+pub(crate) fn ibus_field<T>(f: &[Value<'_>], i: usize) -> Result<T, IBusErr>
+where
+    T: for<'x, 'y> TryFrom<&'x Value<'y>, Error = zvariant::Error>,
+{
+    let v = f
+        .get(i)
+        .ok_or_else(|| IBusErr::new(format!("missing field {}", i)))?;
+    T::try_from(unwrap_variant(v)).map_err(|e| {
+        IBusErr::new(format!(
+            "field {} (signature: {}): {}",
+            i,
+            v.value_signature(),
+            e
+        ))
+    })
+}
+
+/// `av` 数组
+pub(crate) fn ibus_array<'a, 'b>(v: &'a Value<'b>) -> Result<&'a [Value<'b>], IBusErr> {
+    match unwrap_variant(v) {
+        Value::Array(a) => Ok(a.inner()),
+        _ => Err(IBusErr::new(format!(
+            "expected array, got signature: {}",
+            v.value_signature()
+        ))),
+    }
+}
 
-    // 构造外部的 variant  struct
-    // (sa{sv}sv)
-    let st2 = Structure::from((
-        "IBusText",
-        HashMap::<String, Value<'static>>::new(),
-        text,
-        Value::new(st1),
-    ));
+/// `av`: IBus 对象的数组
+pub(crate) fn ibus_array_from<T: IBusSerializable>(items: &[T]) -> Value<'static> {
+    Value::new(items.iter().map(T::serialize).collect::<Vec<_>>())
+}
 
-    Value::new(st2)
+/// 解码 `av` (IBus 对象的数组)
+pub(crate) fn ibus_array_of<T: IBusSerializable>(v: &Value<'_>) -> Result<Vec<T>, IBusErr> {
+    ibus_array(v)?.iter().map(T::deserialize).collect()
 }
 
 // 源文件: `ibus/src/ibustypes.h`
-#[bitfield(u32)]
+#[bitfield(u32, debug)]
+#[derive(PartialEq, Eq)]
 pub struct IBusModifierState {
     /// `IBUS_SHIFT_MASK`: Shift  is activated.
     #[bit(0, rw)]
@@ -146,13 +603,443 @@ impl IBusModifierState {
     }
 }
 
+// 源文件: `ibus/src/ibustypes.h`
+#[bitfield(u32, debug)]
+pub struct IBusCapabilite {
+    /// `IBUS_CAP_PREEDIT_TEXT`: UI is capable to show pre-edit text.
+    #[bit(0, rw)]
+    preedit_text: bool,
+    /// `IBUS_CAP_AUXILIARY_TEXT`: UI is capable to show auxiliary text.
+    #[bit(1, rw)]
+    auxiliary_text: bool,
+    /// `IBUS_CAP_LOOKUP_TABLE`: UI is capable to show the lookup table.
+    #[bit(2, rw)]
+    lookup_table: bool,
+    /// `IBUS_CAP_FOCUS`: UI is capable to get focus.
+    #[bit(3, rw)]
+    focus: bool,
+    /// `IBUS_CAP_PROPERTY`: UI is capable to have property.
+    #[bit(4, rw)]
+    property: bool,
+    /// `IBUS_CAP_SURROUNDING_TEXT`: Client can provide surround text, or IME can handle surround
+    /// text.
+    #[bit(5, rw)]
+    surrounding_text: bool,
+    /// `IBUS_CAP_OSK`: UI is owned by on-screen keyboard.
+    #[bit(6, rw)]
+    osk: bool,
+    /// `IBUS_CAP_SYNC_PROCESS_KEY`: Asynchronous process key events are not supported and the
+    /// `ProcessKeyEvent` return value is used.
+    #[bit(7, rw)]
+    sync_process_key: bool,
+    #[bits(8..=31, rw)]
+    unused: u24,
+}
+
+// 源文件: `ibus/src/ibustypes.h`
+
+/// `IBusInputPurpose`: the purpose of the text field of the client
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum IBusInputPurpose {
+    /// `IBUS_INPUT_PURPOSE_FREE_FORM`: Allow any character.
+    #[default]
+    FreeForm,
+    /// `IBUS_INPUT_PURPOSE_ALPHA`: Allow only alphabetic characters.
+    Alpha,
+    /// `IBUS_INPUT_PURPOSE_DIGITS`: Allow only digits.
+    Digits,
+    /// `IBUS_INPUT_PURPOSE_NUMBER`: Edited field expects numbers.
+    Number,
+    /// `IBUS_INPUT_PURPOSE_PHONE`: Edited field expects phone number.
+    Phone,
+    /// `IBUS_INPUT_PURPOSE_URL`: Edited field expects URL.
+    Url,
+    /// `IBUS_INPUT_PURPOSE_EMAIL`: Edited field expects email address.
+    Email,
+    /// `IBUS_INPUT_PURPOSE_NAME`: Edited field expects the name of a person.
+    Name,
+    /// `IBUS_INPUT_PURPOSE_PASSWORD`: Like `FreeForm`, but characters are hidden.
+    Password,
+    /// `IBUS_INPUT_PURPOSE_PIN`: Like `Digits`, but characters are hidden.
+    Pin,
+    /// `IBUS_INPUT_PURPOSE_TERMINAL`: Allow any character, in addition to control codes.
+    Terminal,
+}
+
+impl IBusInputPurpose {
+    /// True for password and PIN fields: the input method should not capture keys.
+    pub fn is_hidden(self) -> bool {
+        matches!(self, IBusInputPurpose::Password | IBusInputPurpose::Pin)
+    }
+}
+
+impl From<u32> for IBusInputPurpose {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => IBusInputPurpose::Alpha,
+            2 => IBusInputPurpose::Digits,
+            3 => IBusInputPurpose::Number,
+            4 => IBusInputPurpose::Phone,
+            5 => IBusInputPurpose::Url,
+            6 => IBusInputPurpose::Email,
+            7 => IBusInputPurpose::Name,
+            8 => IBusInputPurpose::Password,
+            9 => IBusInputPurpose::Pin,
+            10 => IBusInputPurpose::Terminal,
+            _ => IBusInputPurpose::FreeForm,
+        }
+    }
+}
+
+impl From<IBusInputPurpose> for u32 {
+    fn from(value: IBusInputPurpose) -> Self {
+        match value {
+            IBusInputPurpose::FreeForm => 0,
+            IBusInputPurpose::Alpha => 1,
+            IBusInputPurpose::Digits => 2,
+            IBusInputPurpose::Number => 3,
+            IBusInputPurpose::Phone => 4,
+            IBusInputPurpose::Url => 5,
+            IBusInputPurpose::Email => 6,
+            IBusInputPurpose::Name => 7,
+            IBusInputPurpose::Password => 8,
+            IBusInputPurpose::Pin => 9,
+            IBusInputPurpose::Terminal => 10,
+        }
+    }
+}
+
+// 源文件: `ibus/src/ibustypes.h`
+#[bitfield(u32, debug)]
+pub struct IBusInputHints {
+    /// `IBUS_INPUT_HINT_SPELLCHECK`: Suggest checking for typos.
+    #[bit(0, rw)]
+    spellcheck: bool,
+    /// `IBUS_INPUT_HINT_NO_SPELLCHECK`: Suggest not checking for typos.
+    #[bit(1, rw)]
+    no_spellcheck: bool,
+    /// `IBUS_INPUT_HINT_WORD_COMPLETION`: Suggest word completion.
+    #[bit(2, rw)]
+    word_completion: bool,
+    /// `IBUS_INPUT_HINT_LOWERCASE`: Suggest to convert all text to lowercase.
+    #[bit(3, rw)]
+    lowercase: bool,
+    /// `IBUS_INPUT_HINT_UPPERCASE_CHARS`: Suggest to capitalize all text.
+    #[bit(4, rw)]
+    uppercase_chars: bool,
+    /// `IBUS_INPUT_HINT_UPPERCASE_WORDS`: Suggest to capitalize the first character of each word.
+    #[bit(5, rw)]
+    uppercase_words: bool,
+    /// `IBUS_INPUT_HINT_UPPERCASE_SENTENCES`: Suggest to capitalize the first word of each
+    /// sentence.
+    #[bit(6, rw)]
+    uppercase_sentences: bool,
+    /// `IBUS_INPUT_HINT_INHIBIT_OSK`: Suggest to not show an onscreen keyboard.
+    #[bit(7, rw)]
+    inhibit_osk: bool,
+    /// `IBUS_INPUT_HINT_VERTICAL_WRITING`: The text is vertical.
+    #[bit(8, rw)]
+    vertical_writing: bool,
+    /// `IBUS_INPUT_HINT_EMOJI`: Suggest offering Emoji support.
+    #[bit(9, rw)]
+    emoji: bool,
+    /// `IBUS_INPUT_HINT_NO_EMOJI`: Suggest not offering Emoji support.
+    #[bit(10, rw)]
+    no_emoji: bool,
+    /// `IBUS_INPUT_HINT_PRIVATE`: Request that the input method should not update personalized
+    /// data (like typing history).
+    #[bit(11, rw)]
+    private: bool,
+    #[bits(12..=31, rw)]
+    unused: u20,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use zbus::zvariant::Structure;
 
     #[test]
     fn ibus_text_zvariant_signature() {
-        let v = make_ibus_text("test".into());
+        let v = IBusText::new("test").serialize();
         assert_eq!(v.value_signature(), "(sa{sv}sv)");
     }
+
+    #[test]
+    fn ibus_text_deserialize() {
+        let t = IBusText::new("你好ma")
+            .underline(0..2, IBusAttrUnderline::Single)
+            .foreground(2..4, 0x808080);
+        let v = Value::new(t.serialize());
+        assert_eq!(IBusText::try_from(&v), Ok(t));
+
+        let e = IBusText::try_from(&Value::from("text")).unwrap_err();
+        assert!(e.to_string().contains("got signature: s"));
+    }
+
+    #[test]
+    fn ibus_deserialize_errors() {
+        let e = IBusText::try_from(&IBusAttrList::new().serialize()).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("expected IBusText, got IBusAttrList")
+        );
+
+        let v = Value::new(Structure::from((
+            "IBusText",
+            HashMap::<String, Value<'static>>::new(),
+            "text",
+        )));
+        let e = IBusText::try_from(&v).unwrap_err();
+        assert!(e.to_string().contains("at least 2 fields"));
+
+        let v = Value::new(Structure::from((
+            "IBusText",
+            HashMap::<String, Value<'static>>::new(),
+            1u32,
+            IBusAttrList::new().serialize(),
+        )));
+        let e = IBusText::try_from(&v).unwrap_err();
+        assert!(e.to_string().contains("field 0 (signature: u)"));
+    }
+
+    #[test]
+    fn ibus_content_type() {
+        assert_eq!(IBusInputPurpose::from(8), IBusInputPurpose::Password);
+        assert!(IBusInputPurpose::from(9).is_hidden());
+        assert_eq!(IBusInputPurpose::from(1000), IBusInputPurpose::FreeForm);
+        assert_eq!(u32::from(IBusInputPurpose::Terminal), 10);
+
+        // IBUS_INPUT_HINT_NO_SPELLCHECK | IBUS_INPUT_HINT_LOWERCASE | IBUS_INPUT_HINT_PRIVATE
+        let h = IBusInputHints::new_with_raw_value((1 << 1) | (1 << 3) | (1 << 11));
+        assert!(!h.spellcheck());
+        assert!(h.no_spellcheck());
+        assert!(h.lowercase());
+        assert!(h.private());
+    }
+
+    #[test]
+    fn ibus_capabilite_bits() {
+        // IBUS_CAP_PREEDIT_TEXT | IBUS_CAP_LOOKUP_TABLE | IBUS_CAP_SURROUNDING_TEXT
+        let c = IBusCapabilite::new_with_raw_value(0b0010_0101);
+        assert!(c.preedit_text());
+        assert!(!c.auxiliary_text());
+        assert!(c.lookup_table());
+        assert!(c.surrounding_text());
+        assert!(!c.sync_process_key());
+    }
+
+    #[test]
+    fn ibus_text_attributes() {
+        let t = IBusText::new("你好ma")
+            .underline(0..2, IBusAttrUnderline::Single)
+            .background(2..4, 0xffffff)
+            .serialize();
+        let Value::Structure(st) = t else {
+            panic!("not a structure")
+        };
+        let Value::Value(attrs) = &st.fields()[3] else {
+            panic!("attrs not a variant")
+        };
+        assert_eq!(attrs.value_signature(), "(sa{sv}av)");
+        let Value::Structure(attrs) = attrs.as_ref() else {
+            panic!("attrs not a structure")
+        };
+        let Value::Array(a) = &attrs.fields()[2] else {
+            panic!("not an array")
+        };
+        assert_eq!(a.len(), 2);
+        let Value::Value(a0) = &a[0] else {
+            panic!("not a variant")
+        };
+        assert_eq!(a0.value_signature(), "(sa{sv}uuuu)");
+        assert_eq!(
+            **a0,
+            Value::new(Structure::from((
+                "IBusAttribute",
+                HashMap::<String, Value<'static>>::new(),
+                1u32,
+                1u32,
+                0u32,
+                2u32,
+            )))
+        );
+
+        // 和 IBusText 一样, 范围在前
+        assert_eq!(
+            IBusAttribute::foreground(2..4, 0x808080),
+            IBusAttribute::new(IBusAttr::Foreground(0x808080), 2..4)
+        );
+    }
+
+    #[test]
+    fn ibus_text_attachments() {
+        let t = IBusText::new("喵")
+            .attachment("freq", 10u32)
+            .attachment("pinyin", "miao");
+        // 经过 D-Bus 消息
+        let m = zbus::Message::method_call("/", "Test")
+            .unwrap()
+            .build(&(t.serialize(),))
+            .unwrap();
+        let body = m.body();
+        let v: Value<'_> = body.deserialize().unwrap();
+        let Value::Structure(st) = unwrap_variant(&v) else {
+            panic!("not a structure")
+        };
+        assert_eq!(st.fields()[1].value_signature(), "a{sv}");
+        let o = IBusText::try_from(&v).unwrap();
+        assert_eq!(o.attachments.get("freq"), Some(&Value::U32(10)));
+        assert_eq!(o.attachments.get("pinyin"), Some(&Value::from("miao")));
+        assert_eq!(o, t);
+    }
+
+    mod roundtrip {
+        use super::*;
+        use crate::ibus::{
+            IBusComponent, IBusEngineDesc, IBusOrientation, IBusPropList, IBusPropState,
+            IBusPropType, IBusProperty, LookupTable,
+        };
+        use proptest::collection::{hash_map, vec};
+        use proptest::prelude::*;
+
+        fn attr() -> impl Strategy<Value = IBusAttr> {
+            prop_oneof![
+                (0u32..5).prop_map(|u| IBusAttr::Underline(u.into())),
+                any::<u32>().prop_map(IBusAttr::Foreground),
+                any::<u32>().prop_map(IBusAttr::Background),
+                any::<u32>().prop_map(IBusAttr::Hint),
+            ]
+        }
+
+        fn attachments() -> impl Strategy<Value = IBusAttachments> {
+            let v = prop_oneof![
+                any::<u32>().prop_map(Value::from),
+                ".*".prop_map(Value::from),
+            ];
+            hash_map("[a-z]+", v, 0..3)
+        }
+
+        fn text() -> impl Strategy<Value = IBusText> {
+            let a = (attr(), any::<u32>(), any::<u32>()).prop_map(|(attr, s, e)| IBusAttribute {
+                attr,
+                start_index: s,
+                end_index: e,
+            });
+            (".*", vec(a, 0..4), attachments()).prop_map(|(t, attributes, attachments)| IBusText {
+                text: t,
+                attrs: IBusAttrList { attributes },
+                attachments,
+            })
+        }
+
+        fn table() -> impl Strategy<Value = LookupTable> {
+            (
+                vec(text(), 0..20),
+                vec(text(), 0..3),
+                1u32..=16,
+                any::<(bool, bool)>(),
+                0i32..3,
+                any::<i64>(),
+            )
+                .prop_map(|(c, labels, page_size, (visible, round), o, pos)| {
+                    let mut t = LookupTable::new(vec![], page_size, visible, round).unwrap();
+                    t.extend(c);
+                    t.labels = labels;
+                    t.orientation = IBusOrientation::from(o);
+                    t.set_cursor_pos(pos);
+                    t
+                })
+        }
+
+        fn property() -> impl Strategy<Value = IBusProperty> {
+            (
+                "[a-zA-Z.]*",
+                0u32..5,
+                text(),
+                ".*",
+                text(),
+                any::<(bool, bool)>(),
+                0u32..3,
+                text(),
+            )
+                .prop_map(
+                    |(k, t, label, icon, tooltip, (sensitive, visible), state, symbol)| {
+                        let mut p = IBusProperty::new(k, IBusPropType::from(t), label);
+                        p.icon = icon;
+                        p.tooltip = tooltip;
+                        p.sensitive = sensitive;
+                        p.visible = visible;
+                        p.state = IBusPropState::from(state);
+                        p.symbol = symbol;
+                        p
+                    },
+                )
+        }
+
+        fn engine_desc() -> impl Strategy<Value = IBusEngineDesc> {
+            (vec(".*", 16), any::<u32>()).prop_map(|(s, rank)| {
+                let mut s = s.into_iter();
+                let mut n = || s.next().unwrap();
+                IBusEngineDesc {
+                    name: n(),
+                    longname: n(),
+                    description: n(),
+                    language: n(),
+                    license: n(),
+                    author: n(),
+                    icon: n(),
+                    layout: n(),
+                    rank,
+                    hotkeys: n(),
+                    symbol: n(),
+                    setup: n(),
+                    layout_variant: n(),
+                    layout_option: n(),
+                    version: n(),
+                    textdomain: n(),
+                    icon_prop_key: n(),
+                    attachments: IBusAttachments::new(),
+                }
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn ibus_text(t in text()) {
+                prop_assert_eq!(IBusText::try_from(&t.serialize()), Ok(t));
+            }
+
+            #[test]
+            fn lookup_table(t in table()) {
+                prop_assert_eq!(LookupTable::try_from(&t.serialize()), Ok(t));
+            }
+
+            #[test]
+            fn ibus_prop_list(p in vec(property(), 0..4), sub in vec(property(), 0..4)) {
+                let mut l = IBusPropList { properties: p };
+                if let Some(m) = l.properties.first_mut() {
+                    m.sub_props = IBusPropList { properties: sub };
+                }
+                prop_assert_eq!(IBusPropList::try_from(&l.serialize()), Ok(l));
+            }
+
+            #[test]
+            fn ibus_component(d in vec(engine_desc(), 0..3), s in vec(".*", 8)) {
+                let c = IBusComponent {
+                    name: s[0].clone(),
+                    description: s[1].clone(),
+                    version: s[2].clone(),
+                    license: s[3].clone(),
+                    author: s[4].clone(),
+                    homepage: s[5].clone(),
+                    exec: s[6].clone(),
+                    textdomain: s[7].clone(),
+                    engines: d,
+                    attachments: IBusAttachments::new(),
+                };
+                prop_assert_eq!(IBusComponent::try_from(&c.serialize()), Ok(c));
+            }
+        }
+    }
 }
@@ -0,0 +1,437 @@
+//! `org.freedesktop.IBus.InputContext`: 输入上下文 (客户端, 也就是应用程序这一边)
+use futures_lite::{Stream, StreamExt};
+use pm_bin::log::warn;
+use xkeysym::{KeyCode, Keysym};
+use zbus::{
+    Connection, Message, proxy,
+    proxy::CacheProperties,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+use super::{
+    IBusCapabilite, IBusEngineDesc, IBusErr, IBusExtensionEvent, IBusInputHints, IBusInputPurpose,
+    IBusModifierState, IBusPreeditFocusMode, IBusPropList, IBusPropState, IBusProperty,
+    IBusSerializable, IBusText, LookupTable,
+};
+
+// 源文件: `ibus/bus/inputcontext.c`
+//
+// <node>
+//   <interface name='org.freedesktop.IBus.InputContext'>
+//     <method name='ProcessKeyEvent'>
+//       <arg direction='in'  type='u' name='keyval' />
+//       <arg direction='in'  type='u' name='keycode' />
+//       <arg direction='in'  type='u' name='state' />
+//       <arg direction='out' type='b' name='handled' />
+//     </method>
+//     <method name='SetCursorLocation'>
+//       <arg direction='in' type='i' name='x' />
+//       <arg direction='in' type='i' name='y' />
+//       <arg direction='in' type='i' name='w' />
+//       <arg direction='in' type='i' name='h' />
+//     </method>
+//     <method name='SetCursorLocationRelative'>
+//       <arg direction='in' type='i' name='x' />
+//       <arg direction='in' type='i' name='y' />
+//       <arg direction='in' type='i' name='w' />
+//       <arg direction='in' type='i' name='h' />
+//     </method>
+//     <method name='FocusIn' />
+//     <method name='FocusOut' />
+//     <method name='Reset' />
+//     <method name='SetCapabilities'>
+//       <arg direction='in' type='u' name='caps' />
+//     </method>
+//     <method name='PropertyActivate'>
+//       <arg direction='in' type='s' name='name' />
+//       <arg direction='in' type='u' name='state' />
+//     </method>
+//     <method name='SetEngine'>
+//       <arg direction='in' type='s' name='name' />
+//     </method>
+//     <method name='GetEngine'>
+//       <arg direction='out' type='v' name='desc' />
+//     </method>
+//     <method name='SetSurroundingText'>
+//       <arg direction='in' type='v' name='text' />
+//       <arg direction='in' type='u' name='cursor_pos' />
+//       <arg direction='in' type='u' name='anchor_pos' />
+//     </method>
+//     <signal name='CommitText'>
+//       <arg type='v' name='text' />
+//     </signal>
+//     ...
+//     <property name='ContentType' type='(uu)' access='write' />
+//     <property name='ClientCommitPreedit' type='(b)' access='write' />
+//   </interface>
+// </node>
+#[proxy(
+    interface = "org.freedesktop.IBus.InputContext",
+    default_service = "org.freedesktop.IBus",
+    gen_blocking = false
+)]
+trait InputContext {
+    fn process_key_event(&self, keyval: u32, keycode: u32, state: u32) -> zbus::Result<bool>;
+
+    fn set_cursor_location(&self, x: i32, y: i32, w: i32, h: i32) -> zbus::Result<()>;
+
+    fn set_cursor_location_relative(&self, x: i32, y: i32, w: i32, h: i32) -> zbus::Result<()>;
+
+    fn focus_in(&self) -> zbus::Result<()>;
+
+    fn focus_out(&self) -> zbus::Result<()>;
+
+    fn reset(&self) -> zbus::Result<()>;
+
+    fn set_capabilities(&self, caps: u32) -> zbus::Result<()>;
+
+    fn property_activate(&self, name: &str, state: u32) -> zbus::Result<()>;
+
+    fn set_engine(&self, name: &str) -> zbus::Result<()>;
+
+    fn get_engine(&self) -> zbus::Result<OwnedValue>;
+
+    fn set_surrounding_text(
+        &self,
+        text: &Value<'_>,
+        cursor_pos: u32,
+        anchor_pos: u32,
+    ) -> zbus::Result<()>;
+}
+
+/// A signal emitted by the engine (forwarded by ibus-daemon to the input context)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IBusEvent {
+    CommitText(IBusText),
+    ForwardKeyEvent {
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    },
+    /// `mode` is [`IBusPreeditFocusMode::Clear`] unless `ClientCommitPreedit` is enabled
+    UpdatePreeditText {
+        text: IBusText,
+        cursor_pos: u32,
+        visible: bool,
+        mode: IBusPreeditFocusMode,
+    },
+    ShowPreeditText,
+    HidePreeditText,
+    UpdateAuxiliaryText {
+        text: IBusText,
+        visible: bool,
+    },
+    ShowAuxiliaryText,
+    HideAuxiliaryText,
+    UpdateLookupTable {
+        table: LookupTable,
+        visible: bool,
+    },
+    ShowLookupTable,
+    HideLookupTable,
+    PageUpLookupTable,
+    PageDownLookupTable,
+    CursorUpLookupTable,
+    CursorDownLookupTable,
+    RegisterProperties(IBusPropList),
+    UpdateProperty(Box<IBusProperty>),
+    DeleteSurroundingText {
+        offset: i32,
+        n_chars: u32,
+    },
+    RequireSurroundingText,
+    /// (Engine only) sent to the panel, not to the input context
+    PanelExtension(IBusExtensionEvent),
+}
+
+impl IBusEvent {
+    /// Decodes a D-Bus signal
+    ///
+    /// The interface of the signal is not checked, so this works both for
+    /// `org.freedesktop.IBus.InputContext` and `org.freedesktop.IBus.Engine`.
+    /// Returns `None` for other (unknown) signals.
+    pub fn from_message(m: &Message) -> Result<Option<Self>, IBusErr> {
+        let h = m.header();
+        let Some(member) = h.member() else {
+            return Ok(None);
+        };
+        let b = m.body();
+        let e = |e: zbus::Error| {
+            IBusErr::new(format!("{} (signature: {}): {}", member, b.signature(), e))
+        };
+
+        let o = match member.as_str() {
+            "CommitText" => {
+                let (text,): (Value<'_>,) = b.deserialize().map_err(e)?;
+                Self::CommitText(IBusText::try_from(&text)?)
+            }
+            "ForwardKeyEvent" => {
+                let (keyval, keycode, state): (u32, u32, u32) = b.deserialize().map_err(e)?;
+                Self::ForwardKeyEvent {
+                    keyval: keyval.into(),
+                    keycode: keycode.into(),
+                    state: IBusModifierState::new_with_raw_value(state),
+                }
+            }
+            // 引擎发出的 UpdatePreeditText 信号 (以及 UpdatePreeditTextWithMode) 带有 mode
+            "UpdatePreeditText" | "UpdatePreeditTextWithMode" => {
+                let (text, cursor_pos, visible, mode) = if b.signature() == "vub" {
+                    let (t, c, v): (Value<'_>, u32, bool) = b.deserialize().map_err(e)?;
+                    (t, c, v, IBusPreeditFocusMode::Clear)
+                } else {
+                    let (t, c, v, m): (Value<'_>, u32, bool, u32) = b.deserialize().map_err(e)?;
+                    (t, c, v, m.into())
+                };
+                Self::UpdatePreeditText {
+                    text: IBusText::try_from(&text)?,
+                    cursor_pos,
+                    visible,
+                    mode,
+                }
+            }
+            "ShowPreeditText" => Self::ShowPreeditText,
+            "HidePreeditText" => Self::HidePreeditText,
+            "UpdateAuxiliaryText" => {
+                let (text, visible): (Value<'_>, bool) = b.deserialize().map_err(e)?;
+                Self::UpdateAuxiliaryText {
+                    text: IBusText::try_from(&text)?,
+                    visible,
+                }
+            }
+            "ShowAuxiliaryText" => Self::ShowAuxiliaryText,
+            "HideAuxiliaryText" => Self::HideAuxiliaryText,
+            "UpdateLookupTable" => {
+                let (table, visible): (Value<'_>, bool) = b.deserialize().map_err(e)?;
+                Self::UpdateLookupTable {
+                    table: LookupTable::try_from(&table)?,
+                    visible,
+                }
+            }
+            "ShowLookupTable" => Self::ShowLookupTable,
+            "HideLookupTable" => Self::HideLookupTable,
+            "PageUpLookupTable" => Self::PageUpLookupTable,
+            "PageDownLookupTable" => Self::PageDownLookupTable,
+            "CursorUpLookupTable" => Self::CursorUpLookupTable,
+            "CursorDownLookupTable" => Self::CursorDownLookupTable,
+            "RegisterProperties" => {
+                let (props,): (Value<'_>,) = b.deserialize().map_err(e)?;
+                Self::RegisterProperties(IBusPropList::try_from(&props)?)
+            }
+            "UpdateProperty" => {
+                let (prop,): (Value<'_>,) = b.deserialize().map_err(e)?;
+                Self::UpdateProperty(Box::new(IBusProperty::try_from(&prop)?))
+            }
+            "DeleteSurroundingText" => {
+                let (offset, n_chars): (i32, u32) = b.deserialize().map_err(e)?;
+                Self::DeleteSurroundingText { offset, n_chars }
+            }
+            "RequireSurroundingText" => Self::RequireSurroundingText,
+            "PanelExtension" => {
+                let (data,): (Value<'_>,) = b.deserialize().map_err(e)?;
+                Self::PanelExtension(IBusExtensionEvent::try_from(&data)?)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(o))
+    }
+}
+
+/// Client of an input context (D-Bus interface: `org.freedesktop.IBus.InputContext`)
+///
+/// This is the application side: it sends key events to the current engine, and receives the
+/// text to display ([`IBusEvent`]). Create one with [`crate::ibus::IBusDaemon::input_context`].
+///
+/// <https://ibus.github.io/docs/ibus-1.5/IBusInputContext.html>
+#[derive(Debug, Clone)]
+pub struct IBusInputContext {
+    p: InputContextProxy<'static>,
+}
+
+impl IBusInputContext {
+    /// `path`: as returned by `CreateInputContext`
+    pub async fn new(c: &Connection, path: OwnedObjectPath) -> zbus::Result<Self> {
+        let p = InputContextProxy::builder(c)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        Ok(Self { p })
+    }
+
+    /// D-Bus object path of this input context
+    pub fn path(&self) -> &ObjectPath<'_> {
+        self.p.inner().path()
+    }
+
+    /// Returns true if the key event is handled by the engine
+    pub async fn process_key_event(
+        &self,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> zbus::Result<bool> {
+        self.p
+            .process_key_event(keyval.raw(), keycode.raw(), state.raw_value())
+            .await
+    }
+
+    /// Position of the cursor on the screen
+    pub async fn set_cursor_location(&self, x: i32, y: i32, w: i32, h: i32) -> zbus::Result<()> {
+        self.p.set_cursor_location(x, y, w, h).await
+    }
+
+    /// Position of the cursor, relative to the window of the application
+    pub async fn set_cursor_location_relative(
+        &self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+    ) -> zbus::Result<()> {
+        self.p.set_cursor_location_relative(x, y, w, h).await
+    }
+
+    pub async fn focus_in(&self) -> zbus::Result<()> {
+        self.p.focus_in().await
+    }
+
+    pub async fn focus_out(&self) -> zbus::Result<()> {
+        self.p.focus_out().await
+    }
+
+    pub async fn reset(&self) -> zbus::Result<()> {
+        self.p.reset().await
+    }
+
+    /// What the application can display (preedit text, lookup table, ...)
+    pub async fn set_capabilities(&self, caps: IBusCapabilite) -> zbus::Result<()> {
+        self.p.set_capabilities(caps.raw_value()).await
+    }
+
+    /// Activates a property (menu item) of the engine
+    pub async fn property_activate(&self, name: &str, state: IBusPropState) -> zbus::Result<()> {
+        self.p.property_activate(name, state.into()).await
+    }
+
+    /// Switches the engine of this input context (for example `pmim`)
+    pub async fn set_engine(&self, name: &str) -> zbus::Result<()> {
+        self.p.set_engine(name).await
+    }
+
+    /// The current engine, or error if there is none
+    pub async fn get_engine(&self) -> zbus::Result<IBusEngineDesc> {
+        let v = self.p.get_engine().await?;
+        Ok(IBusEngineDesc::try_from(&*v)?)
+    }
+
+    pub async fn set_surrounding_text(
+        &self,
+        text: impl Into<IBusText>,
+        cursor_pos: u32,
+        anchor_pos: u32,
+    ) -> zbus::Result<()> {
+        self.p
+            .set_surrounding_text(&text.into().serialize(), cursor_pos, anchor_pos)
+            .await
+    }
+
+    /// Sets the `ContentType` property
+    pub async fn set_content_type(
+        &self,
+        purpose: IBusInputPurpose,
+        hints: IBusInputHints,
+    ) -> zbus::Result<()> {
+        let v = (u32::from(purpose), hints.raw_value());
+        Ok(self.p.inner().set_property("ContentType", v).await?)
+    }
+
+    /// If true, the preedit text is committed (not cleared) on focus out, and
+    /// [`IBusEvent::UpdatePreeditText`] carries the mode set by the engine
+    pub async fn set_client_commit_preedit(&self, enabled: bool) -> zbus::Result<()> {
+        let v = (enabled,);
+        Ok(self
+            .p
+            .inner()
+            .set_property("ClientCommitPreedit", v)
+            .await?)
+    }
+
+    /// Stream of all the signals of this input context
+    ///
+    /// Signals which can not be decoded are logged and skipped.
+    pub async fn receive_events(&self) -> zbus::Result<impl Stream<Item = IBusEvent> + use<>> {
+        let s = self.p.inner().receive_all_signals().await?;
+        Ok(s.filter_map(|m| match IBusEvent::from_message(&m) {
+            Ok(e) => e,
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        }))
+    }
+
+    /// Stream of the committed text (signal `CommitText`)
+    pub async fn receive_commit_text(&self) -> zbus::Result<impl Stream<Item = IBusText> + use<>> {
+        let s = self.receive_events().await?;
+        Ok(s.filter_map(|e| match e {
+            IBusEvent::CommitText(t) => Some(t),
+            _ => None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const P: &str = "/org/freedesktop/IBus/InputContext_1";
+    const I: &str = "org.freedesktop.IBus.InputContext";
+
+    #[test]
+    fn ibus_event_from_message() {
+        let m = Message::signal(P, I, "CommitText")
+            .unwrap()
+            .build(&(IBusText::new("喵").serialize(),))
+            .unwrap();
+        assert_eq!(
+            IBusEvent::from_message(&m).unwrap(),
+            Some(IBusEvent::CommitText("喵".into()))
+        );
+
+        let table =
+            LookupTable::new(vec!["一".to_string(), "二".to_string()], 5, true, false).unwrap();
+        let m = Message::signal(P, I, "UpdateLookupTable")
+            .unwrap()
+            .build(&(table.serialize(), true))
+            .unwrap();
+        assert_eq!(
+            IBusEvent::from_message(&m).unwrap(),
+            Some(IBusEvent::UpdateLookupTable {
+                table,
+                visible: true
+            })
+        );
+
+        let m = Message::signal(P, I, "HideLookupTable")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        assert_eq!(
+            IBusEvent::from_message(&m).unwrap(),
+            Some(IBusEvent::HideLookupTable)
+        );
+
+        let m = Message::signal(P, I, "Unknown")
+            .unwrap()
+            .build(&())
+            .unwrap();
+        assert_eq!(IBusEvent::from_message(&m).unwrap(), None);
+
+        // 类型不对
+        let m = Message::signal(P, I, "CommitText")
+            .unwrap()
+            .build(&("喵",))
+            .unwrap();
+        assert!(IBusEvent::from_message(&m).is_err());
+    }
+}
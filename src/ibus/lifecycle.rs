@@ -0,0 +1,57 @@
+//! 连接的生命周期: ibus-daemon 重启 (`ibus restart`) 后重新连接
+use std::error::Error;
+use std::time::Duration;
+
+use async_io::Timer;
+use pm_bin::log::debug;
+
+use super::{IBus, IBusEngine, IBusFactory, get_ibus_addr};
+
+/// 检查 ibus 地址 (文件) 的间隔
+const 检查间隔: Duration = Duration::from_secs(5);
+/// 重新连接的间隔 (等待新的 ibus-daemon 启动)
+const 重连间隔: Duration = Duration::from_secs(1);
+/// 重新连接的次数
+const 重连次数: u32 = 10;
+
+impl<T: IBusEngine + 'static, U: IBusFactory<T> + 'static> IBus<T, U> {
+    /// 等待地址 (文件) 改变, 返回新的地址
+    pub(crate) async fn 地址改变(&self) -> String {
+        loop {
+            Timer::after(检查间隔).await;
+            // 读取失败: ibus-daemon 可能正在重启
+            if let Ok(a) = get_ibus_addr()
+                && a != self.addr()
+            {
+                return a;
+            }
+        }
+    }
+
+    /// Reconnects to the address from [`get_ibus_addr`], retrying several times (while the new
+    /// ibus-daemon is starting)
+    ///
+    /// Returns the last error if all attempts failed.
+    pub async fn reconnect_with_retry(
+        &mut self,
+        mut factory: impl FnMut() -> U,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut 错误 = String::new();
+        for i in 0..重连次数 {
+            Timer::after(重连间隔).await;
+            let a = match get_ibus_addr() {
+                Ok(a) => a,
+                Err(e) => {
+                    错误 = e.to_string();
+                    continue;
+                }
+            };
+            debug!("重新连接 ({}): {}", i + 1, a);
+            match self.reconnect(a, factory()).await {
+                Ok(_) => return Ok(()),
+                Err(e) => 错误 = e.to_string(),
+            }
+        }
+        Err(错误.into())
+    }
+}
@@ -1,10 +1,163 @@
-use std::collections::HashMap;
+use zbus::zvariant::Value;
 
-use zbus::zvariant::{Array, Structure, Value};
+use super::ibus_serde::{ibus_array_from, ibus_array_of, ibus_field, ibus_try_from};
+use super::{IBusAttachments, IBusAttribute, IBusErr, IBusSerializable, IBusText};
 
-use super::ibus_serde::make_ibus_text;
+/// 注释的附件名称
+const ANNOTATION: &str = "annotation";
 
-#[derive(Debug, Copy, Clone)]
+/// A candidate of a [`LookupTable`]
+///
+/// The annotation (for example the pinyin reading or an English gloss) is shown after the
+/// text, separated by a space: the ibus panels (ibus-ui-gtk3, GNOME Shell) only display the text
+/// of a candidate, so the annotation is joined into it. It is also sent as the attachment
+/// `annotation`, for a custom panel which shows it apart (and strips it from the text). A
+/// candidate received from ibus with this attachment is split again.
+///
+/// The payload is not sent to ibus: it only lives in the engine's own table, so a table
+/// received from D-Bus has no payload. It is a `u64` (for example a dictionary id, or an index
+/// into the engine's own data) rather than a generic value, to keep [`LookupTable`] simple.
+///
+/// ```
+/// use librush::ibus::{Candidate, IBusText};
+///
+/// let c = Candidate::new("喵")
+///     .annotation(IBusText::new("miāo").foreground(0..4, 0x808080))
+///     .payload(42);
+/// assert_eq!(IBusText::from(&c), "喵 miāo");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Candidate {
+    /// Text shown to the user, attributes style this candidate only
+    pub text: IBusText,
+    pub annotation: Option<IBusText>,
+    /// Data for the input method (for example a dictionary id), not sent to ibus
+    pub payload: Option<u64>,
+}
+
+impl Candidate {
+    pub fn new(text: impl Into<IBusText>) -> Self {
+        Self {
+            text: text.into(),
+            annotation: None,
+            payload: None,
+        }
+    }
+
+    /// Sets the annotation (or comment)
+    pub fn annotation(mut self, annotation: impl Into<IBusText>) -> Self {
+        self.annotation = Some(annotation.into());
+        self
+    }
+
+    /// Sets the user payload
+    pub fn payload(mut self, payload: u64) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// 从 ibus 收到的 IBusText 拆分出 注释 (payload 丢失)
+    fn from_ibus_text(mut t: IBusText) -> Self {
+        let a = match t.attachments.get(ANNOTATION) {
+            Some(Value::Str(a)) => a.to_string(),
+            _ => return Self::new(t),
+        };
+        let Some(text) = t.text.strip_suffix(a.as_str()) else {
+            return Self::new(t);
+        };
+        let Some(text) = text.strip_suffix(' ') else {
+            return Self::new(t);
+        };
+        t.text = text.to_string();
+        t.attachments.remove(ANNOTATION);
+
+        // 按照位置拆分属性
+        let n = t.char_len();
+        let mut annotation = IBusText::new(a);
+        let attrs = std::mem::take(&mut t.attrs.attributes);
+        for i in attrs {
+            // 跨过空格的属性拆分为两个
+            if i.start_index < n {
+                t.attrs.append(IBusAttribute {
+                    end_index: i.end_index.min(n),
+                    ..i.clone()
+                });
+            }
+            if i.end_index > n + 1 {
+                annotation.attrs.append(IBusAttribute {
+                    start_index: i.start_index.max(n + 1) - n - 1,
+                    end_index: i.end_index - n - 1,
+                    ..i
+                });
+            }
+        }
+        Self {
+            text: t,
+            annotation: Some(annotation),
+            payload: None,
+        }
+    }
+}
+
+/// The text sent to ibus: text and annotation are joined
+impl From<&Candidate> for IBusText {
+    fn from(value: &Candidate) -> Self {
+        let mut t = value.text.clone();
+        if let Some(a) = &value.annotation {
+            let n = t.char_len() + 1;
+            t.text.push(' ');
+            t.text.push_str(&a.text);
+            for i in a.attrs.attributes.iter() {
+                t.attrs.append(IBusAttribute {
+                    start_index: i.start_index + n,
+                    end_index: i.end_index + n,
+                    ..i.clone()
+                });
+            }
+            t.attachments
+                .insert(ANNOTATION.to_string(), Value::from(a.text.clone()));
+        }
+        t
+    }
+}
+
+impl From<IBusText> for Candidate {
+    fn from(value: IBusText) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for Candidate {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for Candidate {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl PartialEq<str> for Candidate {
+    fn eq(&self, other: &str) -> bool {
+        self.text == other
+    }
+}
+
+impl PartialEq<&str> for Candidate {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+impl PartialEq<String> for Candidate {
+    fn eq(&self, other: &String) -> bool {
+        self.text == *other
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum IBusOrientation {
     Horizontal,
     Vertical,
@@ -22,31 +175,44 @@ impl From<IBusOrientation> for i32 {
     }
 }
 
-#[derive(Debug, Clone)]
+impl From<i32> for IBusOrientation {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => IBusOrientation::Horizontal,
+            1 => IBusOrientation::Vertical,
+            _ => IBusOrientation::System,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// A table of strings ("candidates") that IBus displays to the user
 ///
 /// The user can then scroll through the candidates and select one.
 /// IBus automatically paginates candidates by groups of 1 to 16.
 pub struct LookupTable {
-    candidates: Vec<String>,
+    candidates: Vec<Candidate>,
     /// Labels for items in a page
     ///
     /// If this vector is empty (the default), items are labelled 1 through f.
     /// Populating this vector allows to customize labels.
     /// Note that labels are the same on every page, they do not label candidates.
-    pub labels: Vec<String>,
+    pub labels: Vec<IBusText>,
     page_size: u32,
     cursor_pos: u32,
     pub cursor_visible: bool,
     /// If true, scrolling beyond the end of the lookup table wraps to the beginning
     pub round: bool,
     pub orientation: IBusOrientation,
+    pub attachments: IBusAttachments,
 }
 
 impl LookupTable {
     /// Creates a lookup table with cursor at position 0.
     ///
     /// Returns an error if page size is not in `range 1..=16`
+    ///
+    /// Candidates with attributes can be added with [`Self::push_candidate`] or [`Extend`].
     pub fn new(
         candidates: Vec<String>,
         page_size: u32,
@@ -57,47 +223,17 @@ impl LookupTable {
             return Err(());
         }
         Ok(LookupTable {
-            candidates,
+            candidates: candidates.into_iter().map(Candidate::from).collect(),
             labels: vec![],
             page_size,
             cursor_pos: 0,
             cursor_visible,
             round,
             orientation: IBusOrientation::System,
+            attachments: IBusAttachments::new(),
         })
     }
 
-    pub(crate) fn serialize(&self) -> Value<'static> {
-        let special = HashMap::<String, Value<'static>>::new();
-        let candidates: Array = self
-            .candidates
-            .iter()
-            .cloned()
-            .map(make_ibus_text)
-            .collect::<Vec<Value<'static>>>()
-            .into();
-        let labels: Array = self
-            .labels
-            .iter()
-            .cloned()
-            .map(make_ibus_text)
-            .collect::<Vec<Value<'static>>>()
-            .into();
-        let structure = Structure::from((
-            "IBusLookupTable",
-            // a{sv}
-            special,
-            self.page_size,
-            self.cursor_pos,
-            self.cursor_visible,
-            self.round,
-            i32::from(self.orientation),
-            candidates,
-            labels,
-        ));
-        Value::new(structure)
-    }
-
     /// Sets the cursor position, making sure it remains in bound
     #[inline]
     pub fn set_cursor_pos(&mut self, desired_pos: i64) {
@@ -161,7 +297,7 @@ impl LookupTable {
     }
 
     /// Returns the candidate at `index_in_page` in the current page
-    pub fn get_candidate_by_index_in_page(&self, index_in_page: u32) -> Option<&String> {
+    pub fn get_candidate_by_index_in_page(&self, index_in_page: u32) -> Option<&Candidate> {
         if index_in_page >= self.page_size {
             None
         } else {
@@ -185,7 +321,7 @@ impl LookupTable {
     ///
     /// Moves the cursor if the list of candidates is made shorter.
     #[inline]
-    pub fn modify_candidates(&mut self, f: impl FnOnce(&mut Vec<String>)) {
+    pub fn modify_candidates(&mut self, f: impl FnOnce(&mut Vec<Candidate>)) {
         f(&mut self.candidates);
         // if the cursor position is out of bound, make it in bound. Otherwise ibus makes the
         // lookup table disappear
@@ -195,7 +331,7 @@ impl LookupTable {
     }
 
     /// Returns the current list of candidates
-    pub fn candidates(&self) -> &[String] {
+    pub fn candidates(&self) -> &[Candidate] {
         &self.candidates[..]
     }
 
@@ -205,20 +341,69 @@ impl LookupTable {
     }
 
     /// adds one candidate to the end of the list
-    pub fn push_candidate(&mut self, candidate: String) {
-        self.modify_candidates(|c| c.push(candidate))
+    pub fn push_candidate(&mut self, candidate: impl Into<Candidate>) {
+        self.modify_candidates(|c| c.push(candidate.into()))
     }
 
     /// Replaces the list of candidates by this one, and set the cursor to the beginning
-    pub fn reset_candidates(&mut self, candidates: Vec<String>) {
-        self.candidates = candidates;
+    pub fn reset_candidates<T: Into<Candidate>>(&mut self, candidates: Vec<T>) {
+        self.candidates = candidates.into_iter().map(Into::into).collect();
         self.cursor_pos = 0;
     }
 }
 
-impl Extend<String> for LookupTable {
-    fn extend<I: IntoIterator<Item = String>>(&mut self, iter: I) {
-        self.modify_candidates(|c| c.extend(iter))
+// (sa{sv}uubbiavav)
+impl IBusSerializable for LookupTable {
+    const NAME: &'static str = "IBusLookupTable";
+    const MIN_FIELDS: usize = 7;
+
+    fn attachments(&self) -> Option<&IBusAttachments> {
+        Some(&self.attachments)
+    }
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        vec![
+            Value::new(self.page_size),
+            Value::new(self.cursor_pos),
+            Value::new(self.cursor_visible),
+            Value::new(self.round),
+            Value::new(i32::from(self.orientation)),
+            Value::new(
+                self.candidates
+                    .iter()
+                    .map(|c| IBusText::from(c).serialize())
+                    .collect::<Vec<_>>(),
+            ),
+            ibus_array_from(&self.labels),
+        ]
+    }
+
+    fn from_fields(f: &[Value<'_>], attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        let page_size: u32 = ibus_field(f, 0)?;
+        if page_size == 0 || page_size > 16 {
+            return Err(IBusErr::new(format!("bad page_size: {}", page_size)));
+        }
+        Ok(Self {
+            candidates: ibus_array_of::<IBusText>(&f[5])?
+                .into_iter()
+                .map(Candidate::from_ibus_text)
+                .collect(),
+            labels: ibus_array_of(&f[6])?,
+            page_size,
+            cursor_pos: ibus_field(f, 1)?,
+            cursor_visible: ibus_field(f, 2)?,
+            round: ibus_field(f, 3)?,
+            orientation: IBusOrientation::from(ibus_field::<i32>(f, 4)?),
+            attachments,
+        })
+    }
+}
+
+ibus_try_from!(LookupTable);
+
+impl<T: Into<Candidate>> Extend<T> for LookupTable {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.modify_candidates(|c| c.extend(iter.into_iter().map(Into::into)))
     }
 }
 
@@ -422,21 +607,21 @@ fn get_candidate_by_index_in_page() {
     .unwrap();
     assert_eq!(
         table.get_candidate_by_index_in_page(0),
-        Some(&"one".to_string())
+        Some(&Candidate::from("one"))
     );
     assert_eq!(
         table.get_candidate_by_index_in_page(1),
-        Some(&"two".to_string())
+        Some(&Candidate::from("two"))
     );
     assert_eq!(table.get_candidate_by_index_in_page(2), None);
     table.set_cursor_pos(3);
     assert_eq!(
         table.get_candidate_by_index_in_page(0),
-        Some(&"three".to_string())
+        Some(&Candidate::from("three"))
     );
     assert_eq!(
         table.get_candidate_by_index_in_page(1),
-        Some(&"four".to_string())
+        Some(&Candidate::from("four"))
     );
     assert_eq!(table.get_candidate_by_index_in_page(2), None);
 }
@@ -478,9 +663,9 @@ fn modify_candidates_nominal() {
     table.set_cursor_pos(3);
     table.modify_candidates(|c| {
         for candidate in c.iter_mut() {
-            candidate.push('!');
+            candidate.text.text.push('!');
         }
-        c.push("five!!!".to_string());
+        c.push("five!!!".into());
     });
     assert_eq!(table.cursor_pos(), 3);
     assert_eq!(
@@ -594,3 +779,50 @@ fn extend() {
         &["one", "two", "three", "four", "new1", "new2"]
     );
 }
+#[test]
+fn deserialize() {
+    let mut table = LookupTable::new(vec!["one".to_string()], 5, true, false).unwrap();
+    table.labels.push("a".into());
+    table.orientation = IBusOrientation::Vertical;
+    let v = table.serialize();
+    assert_eq!(v.value_signature(), "(sa{sv}uubbiavav)");
+    assert_eq!(LookupTable::try_from(&v).unwrap(), table);
+    assert!(LookupTable::try_from(&IBusText::new("one").serialize()).is_err());
+}
+
+#[test]
+fn candidate_annotation() {
+    let c = Candidate::new(IBusText::new("喵").foreground(0..1, 0xff0000))
+        .annotation(IBusText::new("miāo").foreground(0..4, 0x808080))
+        .payload(42);
+    let t = IBusText::from(&c);
+    assert_eq!(t, "喵 miāo");
+    assert_eq!(t.attrs.attributes[1].start_index, 2);
+    assert_eq!(t.attrs.attributes[1].end_index, 6);
+    assert_eq!(t.attachments.get("annotation"), Some(&Value::from("miāo")));
+
+    let mut table = LookupTable::new(vec!["一".to_string()], 5, true, false).unwrap();
+    table.push_candidate(c.clone());
+    assert_eq!(table.get_candidate_by_index_in_page(1), Some(&c));
+    // payload 不会发送给 ibus
+    let o = LookupTable::try_from(&table.serialize()).unwrap();
+    assert_eq!(o.candidates()[1], Candidate { payload: None, ..c });
+}
+
+#[test]
+fn candidate_attribute_across_annotation() {
+    use super::IBusAttrUnderline;
+
+    // 下划线覆盖 文字, 空格 和 注释
+    let t = IBusText::new("喵喵 miāo")
+        .underline(1..5, IBusAttrUnderline::Single)
+        .attachment("annotation", "miāo");
+    let mut table = LookupTable::new(vec![], 5, true, false).unwrap();
+    table.push_candidate(t);
+    let o = LookupTable::try_from(&table.serialize()).unwrap();
+    assert_eq!(
+        o.candidates()[0],
+        Candidate::new(IBusText::new("喵喵").underline(1..2, IBusAttrUnderline::Single))
+            .annotation(IBusText::new("miāo").underline(0..2, IBusAttrUnderline::Single))
+    );
+}
@@ -2,19 +2,51 @@
 //!
 //! <https://ibus.github.io/docs/ibus-1.5/index.html>
 mod addr;
+#[cfg(any(test, feature = "blocking"))]
+pub mod blocking;
 mod bus;
+mod component;
+mod component_xml;
+mod context;
+mod daemon;
+mod dyn_engine;
 mod engine;
 mod error;
+mod executor;
+mod extension;
 mod factory;
+mod hand_writing;
 mod ibus_serde;
 mod init;
+mod input_context;
+mod lifecycle;
 mod lookup_table;
+mod property;
+mod service;
+mod shutdown;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod virtual_table;
 
 pub use addr::get_ibus_addr;
 pub use bus::IBus;
-pub use engine::{IBusEngine, IBusEngineBackend, IBusPreeditFocusMode};
+pub use component::{IBusComponent, IBusEngineDesc};
+pub use context::EngineContext;
+pub use daemon::IBusDaemon;
+pub use dyn_engine::{BoxedIBusEngine, DynIBusEngine};
+pub use engine::{IBusEngine, IBusEngineBackend, IBusPreeditFocusMode, IBusSurroundingText};
 pub use error::IBusErr;
+pub use executor::{BoxedTask, block_on, set_spawner, spawn};
+pub use extension::{IBusExtensionEvent, IBusExtensionKeys, IBusProcessKeyEventData};
 pub use factory::IBusFactory;
-pub use ibus_serde::IBusModifierState;
-pub use lookup_table::LookupTable;
+pub use hand_writing::{IBusPoint, IBusStroke, StrokeAccumulator};
+pub use ibus_serde::{
+    IBusAttachments, IBusAttr, IBusAttrList, IBusAttrUnderline, IBusAttribute, IBusCapabilite,
+    IBusInputHints, IBusInputPurpose, IBusModifierState, IBusSerializable, IBusText,
+};
+pub use input_context::{IBusEvent, IBusInputContext};
+pub use lookup_table::{Candidate, IBusOrientation, LookupTable};
+pub use property::{IBusPropList, IBusPropState, IBusPropType, IBusProperty};
+pub use shutdown::IBusShutdown;
+pub use virtual_table::{CandidateSource, VirtualLookupTable};
 pub use xkeysym;
@@ -0,0 +1,263 @@
+//! `IBusProperty`: 输入法的属性 (显示在 ibus 面板的菜单中)
+use zbus::zvariant::Value;
+
+use super::ibus_serde::{ibus_array_from, ibus_array_of, ibus_field, ibus_try_from};
+use super::{IBusAttachments, IBusErr, IBusSerializable, IBusText};
+
+// 源文件: `ibus/src/ibusproperty.h`
+
+/// `IBusPropType`: the type of an [`IBusProperty`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IBusPropType {
+    /// `PROP_TYPE_NORMAL`: a simple (clickable) item.
+    Normal,
+    /// `PROP_TYPE_TOGGLE`: an item which can be checked or unchecked.
+    Toggle,
+    /// `PROP_TYPE_RADIO`: an item of a group of exclusive choices.
+    Radio,
+    /// `PROP_TYPE_MENU`: a sub-menu, items are in `sub_props`.
+    Menu,
+    /// `PROP_TYPE_SEPARATOR`: a separator line.
+    Separator,
+}
+
+impl From<IBusPropType> for u32 {
+    fn from(value: IBusPropType) -> Self {
+        match value {
+            IBusPropType::Normal => 0,
+            IBusPropType::Toggle => 1,
+            IBusPropType::Radio => 2,
+            IBusPropType::Menu => 3,
+            IBusPropType::Separator => 4,
+        }
+    }
+}
+
+impl From<u32> for IBusPropType {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => IBusPropType::Toggle,
+            2 => IBusPropType::Radio,
+            3 => IBusPropType::Menu,
+            4 => IBusPropType::Separator,
+            _ => IBusPropType::Normal,
+        }
+    }
+}
+
+/// `IBusPropState`: the state of a toggle or radio [`IBusProperty`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IBusPropState {
+    /// `PROP_STATE_UNCHECKED`
+    Unchecked,
+    /// `PROP_STATE_CHECKED`
+    Checked,
+    /// `PROP_STATE_INCONSISTENT`
+    Inconsistent,
+}
+
+impl From<IBusPropState> for u32 {
+    fn from(value: IBusPropState) -> Self {
+        match value {
+            IBusPropState::Unchecked => 0,
+            IBusPropState::Checked => 1,
+            IBusPropState::Inconsistent => 2,
+        }
+    }
+}
+
+impl From<u32> for IBusPropState {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => IBusPropState::Unchecked,
+            1 => IBusPropState::Checked,
+            _ => IBusPropState::Inconsistent,
+        }
+    }
+}
+
+/// `IBusProperty`: an item of the menu of the input method (in the ibus panel)
+///
+/// When the user clicks it, [`crate::ibus::IBusEngine::property_activate`] is called with `key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IBusProperty {
+    /// Unique identity of the property
+    pub key: String,
+    pub prop_type: IBusPropType,
+    pub label: IBusText,
+    /// Icon name or file path
+    pub icon: String,
+    pub tooltip: IBusText,
+    /// If false, the item is shown but can not be activated
+    pub sensitive: bool,
+    pub visible: bool,
+    pub state: IBusPropState,
+    /// Items of the sub-menu (for [`IBusPropType::Menu`])
+    pub sub_props: IBusPropList,
+    /// Short text shown instead of the icon (for example `中` / `英`)
+    pub symbol: IBusText,
+    pub attachments: IBusAttachments,
+}
+
+impl IBusProperty {
+    /// Creates a visible and sensitive property, with empty icon, tooltip and symbol.
+    pub fn new(
+        key: impl Into<String>,
+        prop_type: IBusPropType,
+        label: impl Into<IBusText>,
+    ) -> Self {
+        Self {
+            key: key.into(),
+            prop_type,
+            label: label.into(),
+            icon: "".to_string(),
+            tooltip: IBusText::default(),
+            sensitive: true,
+            visible: true,
+            state: IBusPropState::Unchecked,
+            sub_props: IBusPropList::new(),
+            symbol: IBusText::default(),
+            attachments: IBusAttachments::new(),
+        }
+    }
+}
+
+// 源文件: `ibus/src/ibusproperty.c`
+// 函数: `ibus_property_serialize()`
+//
+// (sa{sv}suvsvbbuvv)
+impl IBusSerializable for IBusProperty {
+    const NAME: &'static str = "IBusProperty";
+    // 旧版本的 ibus 没有 symbol
+    const MIN_FIELDS: usize = 9;
+
+    fn attachments(&self) -> Option<&IBusAttachments> {
+        Some(&self.attachments)
+    }
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        vec![
+            Value::new(self.key.clone()),
+            Value::new(u32::from(self.prop_type)),
+            Value::new(self.label.serialize()),
+            Value::new(self.icon.clone()),
+            Value::new(self.tooltip.serialize()),
+            Value::new(self.sensitive),
+            Value::new(self.visible),
+            Value::new(u32::from(self.state)),
+            Value::new(self.sub_props.serialize()),
+            // 为了兼容, symbol 在最后
+            Value::new(self.symbol.serialize()),
+        ]
+    }
+
+    fn from_fields(f: &[Value<'_>], attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        let symbol = match f.get(9) {
+            Some(v) => IBusText::deserialize(v)?,
+            None => IBusText::default(),
+        };
+        Ok(Self {
+            key: ibus_field(f, 0)?,
+            prop_type: IBusPropType::from(ibus_field::<u32>(f, 1)?),
+            label: IBusText::deserialize(&f[2])?,
+            icon: ibus_field(f, 3)?,
+            tooltip: IBusText::deserialize(&f[4])?,
+            sensitive: ibus_field(f, 5)?,
+            visible: ibus_field(f, 6)?,
+            state: IBusPropState::from(ibus_field::<u32>(f, 7)?),
+            sub_props: IBusPropList::deserialize(&f[8])?,
+            symbol,
+            attachments,
+        })
+    }
+}
+
+/// `IBusPropList`: a list of [`IBusProperty`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IBusPropList {
+    pub properties: Vec<IBusProperty>,
+}
+
+impl IBusPropList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn append(&mut self, prop: IBusProperty) {
+        self.properties.push(prop);
+    }
+
+    /// Finds a property by key, including in sub-menus
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut IBusProperty> {
+        for p in self.properties.iter_mut() {
+            if p.key == key {
+                return Some(p);
+            }
+            if let Some(p) = p.sub_props.get_mut(key) {
+                return Some(p);
+            }
+        }
+        None
+    }
+}
+
+// (sa{sv}av)
+impl IBusSerializable for IBusPropList {
+    const NAME: &'static str = "IBusPropList";
+    const MIN_FIELDS: usize = 1;
+
+    fn fields(&self) -> Vec<Value<'static>> {
+        vec![ibus_array_from(&self.properties)]
+    }
+
+    fn from_fields(f: &[Value<'_>], _attachments: IBusAttachments) -> Result<Self, IBusErr> {
+        Ok(Self {
+            properties: ibus_array_of(&f[0])?,
+        })
+    }
+}
+
+ibus_try_from!(IBusProperty, IBusPropList);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ibus_property_zvariant_signature() {
+        let p = IBusProperty::new("InputMode", IBusPropType::Normal, "中");
+        assert_eq!(p.serialize().value_signature(), "(sa{sv}suvsvbbuvv)");
+
+        let mut l = IBusPropList::new();
+        l.append(p);
+        assert_eq!(l.serialize().value_signature(), "(sa{sv}av)");
+    }
+
+    #[test]
+    fn ibus_prop_list_get_mut() {
+        let mut m = IBusProperty::new("menu", IBusPropType::Menu, "menu");
+        m.sub_props
+            .append(IBusProperty::new("full", IBusPropType::Radio, "全角"));
+        let mut l = IBusPropList::new();
+        l.append(m);
+
+        l.get_mut("full").unwrap().state = IBusPropState::Checked;
+        assert_eq!(
+            l.properties[0].sub_props.properties[0].state,
+            IBusPropState::Checked
+        );
+        assert!(l.get_mut("none").is_none());
+    }
+
+    #[test]
+    fn ibus_prop_list_deserialize() {
+        let mut m = IBusProperty::new("menu", IBusPropType::Menu, "menu");
+        m.sub_props
+            .append(IBusProperty::new("full", IBusPropType::Radio, "全角"));
+        m.symbol = "喵".into();
+        let mut l = IBusPropList::new();
+        l.append(m);
+
+        assert_eq!(IBusPropList::try_from(&l.serialize()).unwrap(), l);
+    }
+}
@@ -0,0 +1,177 @@
+//! `IBusService`: 每个 ibus 对象 (engine) 都实现的接口
+use std::marker::PhantomData;
+
+use pm_bin::log::{debug, info};
+use zbus::{Connection, ObjectServer, fdo, interface, object_server::SignalEmitter};
+
+use super::IBusEngine;
+use super::context::{engine列表, 删除状态};
+use super::engine::Engine;
+
+/// 调用 `IBusEngine::destroy()`, 然后删除 engine 对象
+///
+/// `destroy()` 出错时也删除, 然后返回错误.
+async fn 销毁engine<T: IBusEngine + 'static>(
+    se: SignalEmitter<'_>,
+    server: &ObjectServer,
+) -> fdo::Result<()> {
+    let p = se.path().to_owned();
+    let c = se.connection().clone();
+
+    let e = server.interface::<_, Engine<T>>(&p).await?;
+    let r = e.get_mut().await.destroy(se, server).await;
+    // 释放引用: 删除时 drop engine
+    drop(e);
+
+    删除状态(&c, &p);
+    let r1 = server.remove::<Engine<T>, _>(&p).await;
+    let r2 = server.remove::<Service<T>, _>(&p).await;
+    info!("销毁 engine: {}", p);
+
+    r?;
+    r1?;
+    r2?;
+    Ok(())
+}
+
+/// 销毁全部 engine (退出之前)
+pub(crate) async fn 销毁全部<T: IBusEngine + 'static>(c: &Connection) {
+    let server = c.object_server();
+    for p in engine列表(c) {
+        if let Ok(se) = SignalEmitter::new(c, p.clone()) {
+            // 忽略错误 (连接可能已经断开)
+            if let Err(e) = 销毁engine::<T>(se, server).await {
+                debug!("销毁 engine {}: {}", p, e);
+            }
+        }
+    }
+}
+
+/// D-Bus interface: `org.freedesktop.IBus.Service`
+///
+/// <https://ibus.github.io/docs/ibus-1.5/IBusService.html>
+#[derive(Debug, Clone)]
+pub(crate) struct Service<T: IBusEngine + 'static> {
+    _t: PhantomData<T>,
+}
+
+impl<T: IBusEngine + 'static> Service<T> {
+    pub fn new() -> Self {
+        Self { _t: PhantomData }
+    }
+}
+
+// 源文件: `ibus/src/ibusservice.c`
+//
+// <node>
+//   <interface name='org.freedesktop.IBus.Service'>
+//     <method name='Destroy' />
+//   </interface>
+// </node>
+#[interface(name = "org.freedesktop.IBus.Service")]
+impl<T: IBusEngine + 'static> Service<T> {
+    /// ibus-daemon 销毁 engine
+    async fn destroy(
+        &self,
+        #[zbus(signal_emitter)] se: SignalEmitter<'_>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        销毁engine::<T>(se, server).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ibus::testing::MockIBus;
+    use crate::ibus::{EngineContext, IBusFactory, block_on};
+    use async_channel::Sender;
+
+    /// `destroy()` 出错的 engine, drop 时通知
+    struct Failing {
+        ctx: Sender<EngineContext>,
+        dropped: Sender<()>,
+    }
+
+    impl IBusEngine for Failing {
+        fn set_context(&mut self, ctx: EngineContext) {
+            let _ = self.ctx.try_send(ctx);
+        }
+
+        async fn destroy(
+            &mut self,
+            _se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+        ) -> fdo::Result<()> {
+            Err(fdo::Error::Failed("destroy".to_string()))
+        }
+    }
+
+    impl Drop for Failing {
+        fn drop(&mut self) {
+            let _ = self.dropped.try_send(());
+        }
+    }
+
+    struct FailingFactory(Sender<EngineContext>, Sender<()>);
+
+    impl IBusFactory<Failing> for FailingFactory {
+        async fn create_engine(&mut self, _name: String) -> Result<Failing, String> {
+            Ok(Failing {
+                ctx: self.0.clone(),
+                dropped: self.1.clone(),
+            })
+        }
+    }
+
+    #[test]
+    fn destroy_hook_fails() {
+        block_on(async {
+            let (tx, rx) = async_channel::unbounded();
+            let (dtx, drx) = async_channel::unbounded();
+            let ibus = MockIBus::new(FailingFactory(tx, dtx)).await.unwrap();
+            let e = ibus.create_engine("failing").await.unwrap();
+            let ctx = rx.recv().await.unwrap();
+
+            assert!(e.destroy().await.is_err());
+            // engine 已经删除
+            drx.recv().await.unwrap();
+            assert!(ctx.is_destroyed());
+        });
+    }
+
+    #[test]
+    fn destroy_all() {
+        block_on(async {
+            let (tx, rx) = async_channel::unbounded();
+            let (dtx, drx) = async_channel::unbounded();
+            let ibus = MockIBus::new(FailingFactory(tx.clone(), dtx.clone()))
+                .await
+                .unwrap();
+            let e1 = ibus.create_engine("failing").await.unwrap();
+            let c1 = rx.recv().await.unwrap();
+            ibus.create_engine("failing").await.unwrap();
+            let c2 = rx.recv().await.unwrap();
+            // 另一个连接
+            let ibus2 = MockIBus::new(FailingFactory(tx, dtx)).await.unwrap();
+            ibus2.create_engine("failing").await.unwrap();
+            let c3 = rx.recv().await.unwrap();
+
+            let _ = e1.destroy().await;
+            drx.recv().await.unwrap();
+            let c = ibus.engine_connection();
+            assert_eq!(engine列表(c), [c2.path().to_string()]);
+
+            // 只销毁这个连接上没有销毁的 engine
+            销毁全部::<Failing>(c).await;
+            drx.recv().await.unwrap();
+            assert!(c1.is_destroyed() && c2.is_destroyed());
+            assert!(!c3.is_destroyed());
+            assert!(engine列表(c).is_empty());
+            assert_eq!(
+                engine列表(ibus2.engine_connection()),
+                [c3.path().to_string()]
+            );
+        });
+    }
+}
@@ -0,0 +1,204 @@
+//! 退出: 名称丢失 (NameLost), ibus-daemon 退出 (Exit), unix 信号 (SIGTERM)
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::pin::Pin;
+use std::sync::{
+    Arc, Mutex, OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+use async_io::Async;
+use futures_lite::{StreamExt, future};
+use pm_bin::log::info;
+use signal_hook::{
+    SigId,
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    flag::register_conditional_default,
+    low_level::{pipe, unregister},
+};
+use zbus::{fdo::DBusProxy, names::BusName};
+
+use super::service::销毁全部;
+use super::{IBus, IBusEngine, IBusFactory};
+
+/// Why [`IBus::run_until_shutdown`] returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IBusShutdown {
+    /// The name was lost (for example taken by another process)
+    NameLost(String),
+    /// The connection was closed (ibus-daemon exited, for example with `Exit`)
+    Disconnected,
+    /// The address of ibus-daemon changed (ibus-daemon restarted), with the new address
+    AddressChanged(String),
+    /// A unix signal was received: `SIGTERM`, `SIGINT` or `SIGHUP`
+    Signal(i32),
+}
+
+impl IBusShutdown {
+    /// Exit code of the process: `128 + signal` for signals (like the shell), otherwise 0
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            IBusShutdown::Signal(s) => (128 + s) as u8,
+            _ => 0,
+        }
+    }
+}
+
+const 信号列表: [i32; 3] = [SIGTERM, SIGINT, SIGHUP];
+
+/// 没有 `信号` 接收时, 执行默认动作 (结束进程)
+///
+/// `unregister` 不会恢复默认动作, 所以注册一次 (conditional default), 然后只修改标志.
+fn 默认动作() -> io::Result<&'static Arc<AtomicBool>> {
+    static 默认: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    static 注册: Mutex<bool> = Mutex::new(false);
+
+    let f = 默认.get_or_init(|| Arc::new(AtomicBool::new(true)));
+    let mut 已注册 = 注册.lock().unwrap_or_else(|e| e.into_inner());
+    if !*已注册 {
+        for i in 信号列表 {
+            register_conditional_default(i, f.clone())?;
+        }
+        *已注册 = true;
+    }
+    Ok(f)
+}
+
+/// 正在接收信号的 `信号` 个数
+static 接收者: Mutex<usize> = Mutex::new(0);
+
+/// 修改接收者个数, 没有接收者时恢复默认动作
+fn 接收者增减(n: isize) -> io::Result<()> {
+    let f = 默认动作()?;
+    let mut c = 接收者.lock().unwrap_or_else(|e| e.into_inner());
+    *c = c.saturating_add_signed(n);
+    f.store(*c == 0, Ordering::SeqCst);
+    Ok(())
+}
+
+/// 接收 unix 信号 (self-pipe), drop 时取消注册 (并恢复默认动作)
+struct 信号 {
+    id: Vec<SigId>,
+    r: Vec<(i32, Async<UnixStream>)>,
+}
+
+impl 信号 {
+    fn new() -> io::Result<Self> {
+        接收者增减(1)?;
+        // 出错时 drop 也会减少接收者
+        let mut s = Self {
+            id: Vec::new(),
+            r: Vec::new(),
+        };
+        for i in 信号列表 {
+            let (r, w) = UnixStream::pair()?;
+            s.id.push(pipe::register(i, w)?);
+            s.r.push((i, Async::new(r)?));
+        }
+        Ok(s)
+    }
+
+    /// 等待任意一个信号
+    async fn recv(&self) -> io::Result<i32> {
+        let mut f: Option<Pin<Box<dyn Future<Output = io::Result<i32>> + Send + '_>>> = None;
+        for (i, r) in self.r.iter() {
+            let w = Box::pin(async move {
+                r.readable().await?;
+                Ok(*i)
+            });
+            f = Some(match f {
+                Some(f) => Box::pin(future::or(f, w)),
+                None => w,
+            });
+        }
+        match f {
+            Some(f) => f.await,
+            None => future::pending().await,
+        }
+    }
+}
+
+impl Drop for 信号 {
+    fn drop(&mut self) {
+        for i in self.id.drain(..) {
+            unregister(i);
+        }
+        // 忽略错误 (已经注册)
+        let _ = 接收者增减(-1);
+    }
+}
+
+impl<T: IBusEngine + 'static, U: IBusFactory<T> + 'static> IBus<T, U> {
+    /// 等待 NameLost (独立运行时没有名称: 一直等待)
+    async fn 名称丢失(&self) -> zbus::Result<IBusShutdown> {
+        let Some(name) = self.name() else {
+            return future::pending().await;
+        };
+        let p = DBusProxy::new(&self.conn()).await?;
+        let mut s = p.receive_name_lost().await?;
+        // 开始接收之前可能已经丢失名称
+        let owner = p.get_name_owner(BusName::try_from(name)?).await.ok();
+        if owner.as_ref().map(|o| o.as_str()) != self.conn().unique_name().map(|u| u.as_str()) {
+            return Ok(IBusShutdown::NameLost(name.to_string()));
+        }
+        while let Some(m) = s.next().await {
+            if m.args()?.name().as_str() == name {
+                return Ok(IBusShutdown::NameLost(name.to_string()));
+            }
+        }
+        // 连接已经关闭
+        Ok(IBusShutdown::Disconnected)
+    }
+
+    /// Runs until the process should exit, then destroys all engines
+    ///
+    /// Returns when the name is lost (`NameLost`), the connection is closed (ibus-daemon
+    /// exited), the address from [`get_ibus_addr`](super::get_ibus_addr) changed (ibus-daemon
+    /// restarted), or `SIGTERM`, `SIGINT` or `SIGHUP` is received. Before returning,
+    /// [`IBusEngine::destroy`] is called for each engine which is still alive.
+    ///
+    /// Use [`IBusShutdown::exit_code`] as the exit code of the process, or reconnect with
+    /// [`IBus::reconnect_with_retry`] after `Disconnected` or `AddressChanged`.
+    pub async fn run_until_shutdown(&self) -> Result<IBusShutdown, Box<dyn Error>> {
+        let s = 信号::new()?;
+
+        let 断开 = async {
+            self.closed().await;
+            Ok(IBusShutdown::Disconnected)
+        };
+        let 地址 = async { Ok(IBusShutdown::AddressChanged(self.地址改变().await)) };
+        let 名称 = async { Ok(self.名称丢失().await?) };
+        let 信号 = async { Ok(IBusShutdown::Signal(s.recv().await?)) };
+        let r: Result<IBusShutdown, Box<dyn Error>> =
+            future::or(future::or(断开, 地址), future::or(名称, 信号)).await;
+        let r = r?;
+        info!("退出: {:?}", r);
+
+        销毁全部::<T>(&self.conn()).await;
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use signal_hook::low_level::raise;
+
+    #[test]
+    fn shutdown_signal() {
+        let s = 信号::new().unwrap();
+        raise(SIGHUP).unwrap();
+        let r = future::block_on(s.recv()).unwrap();
+        assert_eq!(r, SIGHUP);
+        assert_eq!(IBusShutdown::Signal(r).exit_code(), 129);
+        assert_eq!(IBusShutdown::Disconnected.exit_code(), 0);
+        assert_eq!(IBusShutdown::AddressChanged(String::new()).exit_code(), 0);
+
+        // drop 以后恢复默认动作
+        assert!(!默认动作().unwrap().load(Ordering::SeqCst));
+        drop(s);
+        assert!(默认动作().unwrap().load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,699 @@
+//! 测试: 模拟 ibus-daemon, 不需要桌面环境 (feature `testing`)
+//!
+//! ```
+//! use librush::ibus::testing::MockIBus;
+//! use librush::ibus::{IBusEngine, IBusEvent, IBusFactory};
+//!
+//! struct Nothing;
+//! impl IBusEngine for Nothing {}
+//!
+//! struct Factory;
+//! impl IBusFactory<Nothing> for Factory {
+//!     async fn create_engine(&mut self, _name: String) -> Result<Nothing, String> {
+//!         Ok(Nothing)
+//!     }
+//! }
+//!
+//! # librush::ibus::block_on(async {
+//! let ibus = MockIBus::new(Factory).await.unwrap();
+//! let mut e = ibus.create_engine("nothing").await.unwrap();
+//! e.focus_in().await.unwrap();
+//! assert!(!e.type_str("a").await.unwrap());
+//! assert_eq!(e.events().await.unwrap(), Vec::<IBusEvent>::new());
+//! # });
+//! ```
+use futures_lite::{StreamExt, future};
+use xkeysym::{KeyCode, Keysym};
+use zbus::{
+    Connection, Guid, Message, MessageStream,
+    connection::Builder,
+    message::Type,
+    zvariant::{DynamicType, OwnedObjectPath, OwnedValue, Value},
+};
+
+use super::extension::extension_keys_to;
+use super::factory::{Factory, IBUS_PATH_FACTORY};
+use super::{
+    IBusCapabilite, IBusEngine, IBusEvent, IBusExtensionEvent, IBusExtensionKeys, IBusFactory,
+    IBusInputHints, IBusInputPurpose, IBusModifierState, IBusPropState, IBusSerializable,
+    IBusStroke, IBusText,
+};
+
+const IFACE_FACTORY: &str = "org.freedesktop.IBus.Factory";
+const IFACE_ENGINE: &str = "org.freedesktop.IBus.Engine";
+const IFACE_SERVICE: &str = "org.freedesktop.IBus.Service";
+
+/// 一对 (点对点) 连接: (daemon, engine)
+///
+/// 在连接之前注册 factory, 否则 daemon 的第一个调用可能丢失
+async fn 连接对<T: IBusEngine + 'static, U: IBusFactory<T> + 'static>(
+    factory: U,
+) -> zbus::Result<(Connection, Connection)> {
+    #[cfg(feature = "tokio")]
+    let (a, b) = {
+        let (a, b) = tokio::net::UnixStream::pair()?;
+        (Builder::unix_stream(a), Builder::unix_stream(b))
+    };
+    #[cfg(not(feature = "tokio"))]
+    let (a, b) = {
+        let (a, b) = std::os::unix::net::UnixStream::pair()?;
+        (
+            Builder::async_io_unix_stream(a),
+            Builder::async_io_unix_stream(b),
+        )
+    };
+
+    let b = b.p2p().serve_at(IBUS_PATH_FACTORY, Factory::new(factory))?;
+    future::try_zip(a.server(Guid::generate())?.p2p().build(), b.build()).await
+}
+
+/// A fake ibus-daemon, for testing an [`IBusEngine`] without a desktop session
+///
+/// The factory is served on a peer-to-peer D-Bus connection (no bus, no `DISPLAY`), and the
+/// mock daemon calls the engines directly, see [`MockEngine`].
+#[derive(Debug, Clone)]
+pub struct MockIBus {
+    c: Connection,
+    _e: Connection,
+}
+
+impl MockIBus {
+    /// Registers `factory`, like [`crate::ibus::IBus::new`] does with a real ibus-daemon
+    pub async fn new<T: IBusEngine + 'static, U: IBusFactory<T> + 'static>(
+        factory: U,
+    ) -> zbus::Result<Self> {
+        let (c, e) = 连接对(factory).await?;
+        Ok(Self { c, _e: e })
+    }
+
+    /// engine 一侧的连接
+    #[cfg(test)]
+    pub(crate) fn engine_connection(&self) -> &Connection {
+        &self._e
+    }
+
+    /// Calls `CreateEngine` on the factory
+    pub async fn create_engine(&self, name: &str) -> zbus::Result<MockEngine> {
+        // 在调用之前开始接收信号
+        let s = MessageStream::from(&self.c);
+        let r = self
+            .c
+            .call_method(
+                None::<&str>,
+                IBUS_PATH_FACTORY,
+                Some(IFACE_FACTORY),
+                "CreateEngine",
+                &(name,),
+            )
+            .await?;
+        let path: OwnedObjectPath = r.body().deserialize()?;
+        Ok(MockEngine {
+            c: self.c.clone(),
+            path,
+            s,
+            events: Vec::new(),
+        })
+    }
+}
+
+/// An engine created by [`MockIBus`]
+///
+/// The methods call the engine as ibus-daemon would do. Every signal emitted by the engine is
+/// recorded as an [`IBusEvent`], see [`Self::events`].
+#[derive(Debug)]
+pub struct MockEngine {
+    c: Connection,
+    path: OwnedObjectPath,
+    s: MessageStream,
+    events: Vec<IBusEvent>,
+}
+
+impl MockEngine {
+    /// D-Bus object path of the engine
+    pub fn path(&self) -> &OwnedObjectPath {
+        &self.path
+    }
+
+    /// 收到的信号 (不等待)
+    async fn 接收(&mut self) -> zbus::Result<()> {
+        while let Some(Some(m)) = future::poll_once(self.s.next()).await {
+            self.记录(&m?)?;
+        }
+        Ok(())
+    }
+
+    fn 记录(&mut self, m: &Message) -> zbus::Result<()> {
+        let h = m.header();
+        if h.message_type() != Type::Signal || h.path() != Some(&self.path) {
+            return Ok(());
+        }
+        if let Some(e) = IBusEvent::from_message(m)? {
+            self.events.push(e);
+        }
+        Ok(())
+    }
+
+    async fn call<B>(&mut self, iface: &str, method: &str, body: &B) -> zbus::Result<Message>
+    where
+        B: serde::ser::Serialize + DynamicType,
+    {
+        let r = self
+            .c
+            .call_method(None::<&str>, &self.path, Some(iface), method, body)
+            .await?;
+        // 信号在方法返回之前发出, 所以此时已经收到
+        self.接收().await?;
+        Ok(r)
+    }
+
+    /// Returns (and clears) the signals recorded so far
+    pub async fn events(&mut self) -> zbus::Result<Vec<IBusEvent>> {
+        self.接收().await?;
+        Ok(std::mem::take(&mut self.events))
+    }
+
+    /// Waits for the next signal (for signals emitted outside of the callbacks)
+    pub async fn next_event(&mut self) -> zbus::Result<IBusEvent> {
+        self.接收().await?;
+        while self.events.is_empty() {
+            match self.s.next().await {
+                Some(m) => self.记录(&m?)?,
+                None => return Err(zbus::Error::Failure("connection closed".to_string())),
+            }
+        }
+        Ok(self.events.remove(0))
+    }
+
+    pub async fn process_key_event(
+        &mut self,
+        keyval: Keysym,
+        keycode: KeyCode,
+        state: IBusModifierState,
+    ) -> zbus::Result<bool> {
+        let body = (keyval.raw(), keycode.raw(), state.raw_value());
+        let r = self.call(IFACE_ENGINE, "ProcessKeyEvent", &body).await?;
+        r.body().deserialize()
+    }
+
+    /// Sends the key press and release events of each char (keycode 0, no modifier)
+    ///
+    /// Returns true if all the key presses are handled by the engine.
+    pub async fn type_str(&mut self, s: &str) -> zbus::Result<bool> {
+        let mut handled = true;
+        for c in s.chars() {
+            let k = Keysym::from_char(c);
+            let down = IBusModifierState::new_with_raw_value(0);
+            handled &= self.process_key_event(k, KeyCode::new(0), down).await?;
+            let up = down.with_release(true);
+            self.process_key_event(k, KeyCode::new(0), up).await?;
+        }
+        Ok(handled)
+    }
+
+    pub async fn set_cursor_location(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+    ) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "SetCursorLocation", &(x, y, w, h))
+            .await?;
+        Ok(())
+    }
+
+    /// Sends one stroke (`ProcessHandWritingEvent`)
+    pub async fn process_hand_writing_event(&mut self, stroke: &IBusStroke) -> zbus::Result<()> {
+        let body = (stroke.coordinates(),);
+        self.call(IFACE_ENGINE, "ProcessHandWritingEvent", &body)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn cancel_hand_writing(&mut self, n_strokes: u32) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "CancelHandWriting", &(n_strokes,))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn panel_extension_received(
+        &mut self,
+        event: &IBusExtensionEvent,
+    ) -> zbus::Result<()> {
+        let body = (event.serialize(),);
+        self.call(IFACE_ENGINE, "PanelExtensionReceived", &body)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn panel_extension_register_keys(
+        &mut self,
+        keys: &IBusExtensionKeys,
+    ) -> zbus::Result<()> {
+        let body = (extension_keys_to(keys),);
+        self.call(IFACE_ENGINE, "PanelExtensionRegisterKeys", &body)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_capabilities(&mut self, caps: IBusCapabilite) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "SetCapabilities", &(caps.raw_value(),))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_surrounding_text(
+        &mut self,
+        text: impl Into<IBusText>,
+        cursor_pos: u32,
+        anchor_pos: u32,
+    ) -> zbus::Result<()> {
+        let body = (text.into().serialize(), cursor_pos, anchor_pos);
+        self.call(IFACE_ENGINE, "SetSurroundingText", &body).await?;
+        Ok(())
+    }
+
+    /// Sets the `ContentType` property
+    pub async fn set_content_type(
+        &mut self,
+        purpose: IBusInputPurpose,
+        hints: IBusInputHints,
+    ) -> zbus::Result<()> {
+        let v = Value::new((u32::from(purpose), hints.raw_value()));
+        let body = (IFACE_ENGINE, "ContentType", v);
+        self.call("org.freedesktop.DBus.Properties", "Set", &body)
+            .await?;
+        Ok(())
+    }
+
+    /// Reads the `ActiveSurroundingText` property: true once the engine asked for the
+    /// surrounding text
+    pub async fn active_surrounding_text(&mut self) -> zbus::Result<bool> {
+        let body = (IFACE_ENGINE, "ActiveSurroundingText");
+        let r = self
+            .call("org.freedesktop.DBus.Properties", "Get", &body)
+            .await?;
+        let (v,): (OwnedValue,) = r.body().deserialize()?;
+        Ok(bool::try_from(v)?)
+    }
+
+    pub async fn property_activate(
+        &mut self,
+        name: &str,
+        state: IBusPropState,
+    ) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "PropertyActivate", &(name, u32::from(state)))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn property_show(&mut self, name: &str) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "PropertyShow", &(name,)).await?;
+        Ok(())
+    }
+
+    pub async fn property_hide(&mut self, name: &str) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "PropertyHide", &(name,)).await?;
+        Ok(())
+    }
+
+    pub async fn candidate_clicked(
+        &mut self,
+        index: u32,
+        button: u32,
+        state: u32,
+    ) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "CandidateClicked", &(index, button, state))
+            .await?;
+        Ok(())
+    }
+
+    async fn call0(&mut self, method: &str) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, method, &()).await?;
+        Ok(())
+    }
+
+    pub async fn focus_in(&mut self) -> zbus::Result<()> {
+        self.call0("FocusIn").await
+    }
+
+    pub async fn focus_out(&mut self) -> zbus::Result<()> {
+        self.call0("FocusOut").await
+    }
+
+    /// `FocusInId`: ibus-daemon uses it instead of `FocusIn` (the engine has `FocusId`)
+    pub async fn focus_in_id(&mut self, object_path: &str, client: &str) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "FocusInId", &(object_path, client))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn focus_out_id(&mut self, object_path: &str) -> zbus::Result<()> {
+        self.call(IFACE_ENGINE, "FocusOutId", &(object_path,))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn reset(&mut self) -> zbus::Result<()> {
+        self.call0("Reset").await
+    }
+
+    pub async fn enable(&mut self) -> zbus::Result<()> {
+        self.call0("Enable").await
+    }
+
+    pub async fn disable(&mut self) -> zbus::Result<()> {
+        self.call0("Disable").await
+    }
+
+    pub async fn page_up(&mut self) -> zbus::Result<()> {
+        self.call0("PageUp").await
+    }
+
+    pub async fn page_down(&mut self) -> zbus::Result<()> {
+        self.call0("PageDown").await
+    }
+
+    pub async fn cursor_up(&mut self) -> zbus::Result<()> {
+        self.call0("CursorUp").await
+    }
+
+    pub async fn cursor_down(&mut self) -> zbus::Result<()> {
+        self.call0("CursorDown").await
+    }
+
+    /// Destroys the engine (`org.freedesktop.IBus.Service.Destroy`)
+    pub async fn destroy(mut self) -> zbus::Result<()> {
+        self.call(IFACE_SERVICE, "Destroy", &()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ibus::{
+        IBusEngineBackend, IBusPreeditFocusMode, IBusProcessKeyEventData, StrokeAccumulator,
+        block_on,
+    };
+    use zbus::{ObjectServer, fdo, object_server::SignalEmitter};
+
+    /// 测试用的 engine: 字母进入 preedit, 空格上屏; 手写时显示笔画数
+    #[derive(Default)]
+    struct Echo {
+        s: String,
+        strokes: StrokeAccumulator,
+        emoji_keys: Vec<IBusProcessKeyEventData>,
+    }
+
+    impl Echo {
+        async fn show_strokes(&self, se: &SignalEmitter<'_>) -> fdo::Result<()> {
+            let n = self.strokes.strokes().len();
+            Self::update_auxiliary_text(se, n.to_string(), n > 0).await?;
+            Ok(())
+        }
+    }
+
+    impl IBusEngine for Echo {
+        async fn process_key_event(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            keyval: Keysym,
+            keycode: KeyCode,
+            state: IBusModifierState,
+        ) -> fdo::Result<bool> {
+            if state.is_keyup() {
+                return Ok(false);
+            }
+            if self
+                .emoji_keys
+                .iter()
+                .any(|k| k.keyval == keyval && k.state == state)
+            {
+                Self::panel_extension(&se, &IBusExtensionEvent::new("emoji", true)).await?;
+                return Ok(true);
+            }
+            match keyval.key_char() {
+                Some(' ') => {
+                    Self::commit_text(&se, std::mem::take(&mut self.s)).await?;
+                    Self::update_preedit_text(&se, "", 0, false, IBusPreeditFocusMode::Clear)
+                        .await?;
+                }
+                // 上屏, 然后把标点符号还给应用程序
+                Some('.') => {
+                    Self::commit_text(&se, std::mem::take(&mut self.s)).await?;
+                    Self::forward_key_press_release(&se, keyval, keycode, state).await?;
+                }
+                Some(c) if c.is_ascii_lowercase() => {
+                    self.s.push(c);
+                    let n = self.s.len() as u32;
+                    Self::update_preedit_text(
+                        &se,
+                        self.s.as_str(),
+                        n,
+                        true,
+                        IBusPreeditFocusMode::Clear,
+                    )
+                    .await?;
+                }
+                _ => return Ok(false),
+            }
+            Ok(true)
+        }
+
+        async fn process_hand_writing_event(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            stroke: IBusStroke,
+        ) -> fdo::Result<()> {
+            self.strokes.push(stroke);
+            self.show_strokes(&se).await
+        }
+
+        async fn cancel_hand_writing(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            n_strokes: u32,
+        ) -> fdo::Result<()> {
+            self.strokes.cancel(n_strokes);
+            self.show_strokes(&se).await
+        }
+
+        // 按应用程序区分
+        async fn focus_in_id(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            _object_path: String,
+            client: String,
+        ) -> fdo::Result<()> {
+            Self::update_auxiliary_text(&se, client, true).await?;
+            Ok(())
+        }
+
+        // focus_out_id 默认调用 focus_out
+        async fn focus_out(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+        ) -> fdo::Result<()> {
+            Self::update_auxiliary_text(&se, "", false).await?;
+            Ok(())
+        }
+
+        async fn panel_extension_register_keys(
+            &mut self,
+            _se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            mut keys: IBusExtensionKeys,
+        ) -> fdo::Result<()> {
+            self.emoji_keys = keys.remove("emoji").unwrap_or_default();
+            Ok(())
+        }
+
+        async fn panel_extension_received(
+            &mut self,
+            se: SignalEmitter<'_>,
+            _server: &ObjectServer,
+            event: IBusExtensionEvent,
+        ) -> fdo::Result<()> {
+            Self::update_auxiliary_text(&se, event.name, event.is_enabled).await?;
+            Ok(())
+        }
+    }
+
+    struct EchoFactory;
+
+    impl IBusFactory<Echo> for EchoFactory {
+        async fn create_engine(&mut self, _name: String) -> Result<Echo, String> {
+            Ok(Echo::default())
+        }
+    }
+
+    fn preedit(s: &str, visible: bool) -> IBusEvent {
+        IBusEvent::UpdatePreeditText {
+            text: s.into(),
+            cursor_pos: s.len() as u32,
+            visible,
+            mode: IBusPreeditFocusMode::Clear,
+        }
+    }
+
+    fn aux(s: &str, visible: bool) -> IBusEvent {
+        IBusEvent::UpdateAuxiliaryText {
+            text: s.into(),
+            visible,
+        }
+    }
+
+    #[test]
+    fn mock_ibus_transcript() {
+        block_on(async {
+            let ibus = MockIBus::new(EchoFactory).await.unwrap();
+            let mut e = ibus.create_engine("echo").await.unwrap();
+
+            assert!(e.type_str("ab ").await.unwrap());
+            assert!(!e.type_str("1").await.unwrap());
+            assert_eq!(
+                e.events().await.unwrap(),
+                vec![
+                    preedit("a", true),
+                    preedit("ab", true),
+                    IBusEvent::CommitText("ab".into()),
+                    IBusEvent::UpdatePreeditText {
+                        text: "".into(),
+                        cursor_pos: 0,
+                        visible: false,
+                        mode: IBusPreeditFocusMode::Clear,
+                    },
+                ]
+            );
+            assert!(e.events().await.unwrap().is_empty());
+
+            // 每个 engine 只收到自己的信号
+            let mut e2 = ibus.create_engine("echo").await.unwrap();
+            assert_ne!(e.path(), e2.path());
+            e2.type_str("c").await.unwrap();
+            assert!(e.events().await.unwrap().is_empty());
+            assert_eq!(e2.events().await.unwrap(), vec![preedit("c", true)]);
+
+            e.type_str("d.").await.unwrap();
+            let k = Keysym::from_char('.');
+            let up = IBusModifierState::new_with_raw_value(0).with_release(true);
+            assert_eq!(
+                e.events().await.unwrap()[1..],
+                [
+                    IBusEvent::CommitText("d".into()),
+                    IBusEvent::ForwardKeyEvent {
+                        keyval: k,
+                        keycode: KeyCode::new(0),
+                        state: IBusModifierState::new_with_raw_value(0),
+                    },
+                    IBusEvent::ForwardKeyEvent {
+                        keyval: k,
+                        keycode: KeyCode::new(0),
+                        state: up,
+                    },
+                ]
+            );
+
+            e.destroy().await.unwrap();
+            e2.focus_in().await.unwrap();
+        });
+    }
+
+    // 手写
+    #[test]
+    fn mock_ibus_hand_writing() {
+        block_on(async {
+            let ibus = MockIBus::new(EchoFactory).await.unwrap();
+            let mut e = ibus.create_engine("echo").await.unwrap();
+
+            for _ in 0..3 {
+                let stroke = IBusStroke::from_coordinates(&[0.1, 0.5, 0.9, 0.5]);
+                e.process_hand_writing_event(&stroke).await.unwrap();
+            }
+            e.cancel_hand_writing(2).await.unwrap();
+            e.cancel_hand_writing(0).await.unwrap();
+            assert_eq!(
+                e.events().await.unwrap(),
+                [
+                    aux("1", true),
+                    aux("2", true),
+                    aux("3", true),
+                    aux("1", true),
+                    aux("0", false)
+                ]
+            );
+
+            e.destroy().await.unwrap();
+        });
+    }
+
+    // 面板扩展: ctrl+. 打开 emoji 选择器
+    #[test]
+    fn mock_ibus_panel_extension() {
+        block_on(async {
+            let ibus = MockIBus::new(EchoFactory).await.unwrap();
+            let mut e = ibus.create_engine("echo").await.unwrap();
+
+            let ctrl = IBusModifierState::new_with_raw_value(0).with_control(true);
+            let k = IBusProcessKeyEventData {
+                keyval: Keysym::period,
+                keycode: KeyCode::new(52),
+                state: ctrl,
+            };
+            let keys = IBusExtensionKeys::from([("emoji".to_string(), vec![k])]);
+            e.panel_extension_register_keys(&keys).await.unwrap();
+            assert!(
+                e.process_key_event(Keysym::period, KeyCode::new(52), ctrl)
+                    .await
+                    .unwrap()
+            );
+            e.panel_extension_received(&IBusExtensionEvent::new("emoji", false))
+                .await
+                .unwrap();
+            assert_eq!(
+                e.events().await.unwrap(),
+                [
+                    IBusEvent::PanelExtension(IBusExtensionEvent::new("emoji", true)),
+                    aux("emoji", false),
+                ]
+            );
+
+            e.destroy().await.unwrap();
+        });
+    }
+
+    // FocusInId / FocusOutId
+    #[test]
+    fn mock_ibus_focus_id() {
+        block_on(async {
+            let ibus = MockIBus::new(EchoFactory).await.unwrap();
+            let mut e = ibus.create_engine("echo").await.unwrap();
+
+            let r = e
+                .call(
+                    "org.freedesktop.DBus.Properties",
+                    "Get",
+                    &(IFACE_ENGINE, "FocusId"),
+                )
+                .await
+                .unwrap();
+            let (v,): (zbus::zvariant::OwnedValue,) = r.body().deserialize().unwrap();
+            assert_eq!(*v, Value::Bool(true));
+            let ic = "/org/freedesktop/IBus/InputContext_1";
+            e.focus_in_id(ic, "gtk3-im:kitty").await.unwrap();
+            e.focus_out_id(ic).await.unwrap();
+            assert_eq!(
+                e.events().await.unwrap(),
+                [aux("gtk3-im:kitty", true), aux("", false)]
+            );
+
+            e.destroy().await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,281 @@
+//! 虚拟候选表: 只发送 光标附近 的候选项
+use std::ops::Range;
+
+use super::{Candidate, IBusAttachments, IBusOrientation, IBusText, LookupTable};
+
+/// A (possibly lazy) list of candidates for a [`VirtualLookupTable`]
+pub trait CandidateSource {
+    /// Total number of candidates
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the candidates in `range` (always inside `0..self.len()`)
+    fn candidates(&self, range: Range<usize>) -> Vec<Candidate>;
+}
+
+impl CandidateSource for Vec<Candidate> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn candidates(&self, range: Range<usize>) -> Vec<Candidate> {
+        self[range].to_vec()
+    }
+}
+
+/// A lookup table which only sends the current page (or a few pages around it) to ibus
+///
+/// The cursor, paging and wrap-around work on the full list, like [`LookupTable`]. Candidates
+/// are pulled from the [`CandidateSource`] when [`Self::lookup_table`] is called.
+///
+/// ```
+/// use librush::ibus::{Candidate, VirtualLookupTable};
+///
+/// let c: Vec<Candidate> = (0..10000).map(|i| Candidate::new(i.to_string())).collect();
+/// let mut t = VirtualLookupTable::new(c, 5, true, false).unwrap();
+/// t.page_down();
+/// assert_eq!(t.cursor_pos(), 5);
+///
+/// let page = t.lookup_table();
+/// assert_eq!(page.candidates(), &["5", "6", "7", "8", "9"]);
+/// assert_eq!(page.cursor_pos(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VirtualLookupTable<S: CandidateSource> {
+    source: S,
+    /// Labels for items in a page, see [`LookupTable::labels`]
+    pub labels: Vec<IBusText>,
+    page_size: u32,
+    cursor_pos: u32,
+    pub cursor_visible: bool,
+    /// If true, scrolling beyond the end of the lookup table wraps to the beginning
+    pub round: bool,
+    pub orientation: IBusOrientation,
+    pub attachments: IBusAttachments,
+    /// Number of pages sent before and after the current page (default 0: current page only)
+    pub window: u32,
+}
+
+impl<S: CandidateSource> VirtualLookupTable<S> {
+    /// Creates a lookup table with cursor at position 0.
+    ///
+    /// Returns an error if page size is not in `range 1..=16`
+    // 和 `LookupTable::new` 一样
+    #[allow(clippy::result_unit_err)]
+    pub fn new(source: S, page_size: u32, cursor_visible: bool, round: bool) -> Result<Self, ()> {
+        if page_size == 0 || page_size > 16 {
+            return Err(());
+        }
+        Ok(Self {
+            source,
+            labels: vec![],
+            page_size,
+            cursor_pos: 0,
+            cursor_visible,
+            round,
+            orientation: IBusOrientation::System,
+            attachments: IBusAttachments::new(),
+            window: 0,
+        })
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Replaces the source, and set the cursor to the beginning
+    pub fn reset_source(&mut self, source: S) {
+        self.source = source;
+        self.cursor_pos = 0;
+    }
+
+    /// Total number of candidates
+    pub fn len(&self) -> usize {
+        self.source.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.source.is_empty()
+    }
+
+    /// Sets the cursor position, making sure it remains in bound
+    pub fn set_cursor_pos(&mut self, desired_pos: i64) {
+        let len = self.source.len() as i64;
+        if self.round {
+            if len == 0 {
+                self.cursor_pos = 0
+            } else {
+                self.cursor_pos = desired_pos.rem_euclid(len) as u32
+            }
+        } else {
+            self.cursor_pos = desired_pos.min(len - 1).max(0) as u32
+        }
+    }
+
+    /// Sets the cursor position to the next element
+    pub fn cursor_down(&mut self) {
+        self.set_cursor_pos(self.cursor_pos as i64 + 1)
+    }
+
+    /// Sets the cursor position to the previous element
+    pub fn cursor_up(&mut self) {
+        self.set_cursor_pos(self.cursor_pos as i64 - 1)
+    }
+
+    /// Sets the cursor position on an element of the next page
+    pub fn page_down(&mut self) {
+        self.set_cursor_pos(
+            self.cursor_pos as i64 - (self.cursor_pos % self.page_size) as i64
+                + self.page_size as i64,
+        )
+    }
+
+    /// Sets the cursor position on an element of the previous page
+    pub fn page_up(&mut self) {
+        self.set_cursor_pos(self.cursor_pos as i64 - (self.cursor_pos % self.page_size) as i64 - 1)
+    }
+
+    /// The index of the currently selected candidate (in the full list)
+    pub fn cursor_pos(&self) -> u32 {
+        self.cursor_pos
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Sets the page size. Fails if the page size is 0 or more than 16.
+    #[allow(clippy::result_unit_err)]
+    pub fn set_page_size(&mut self, page_size: u32) -> Result<(), ()> {
+        if page_size == 0 || page_size > 16 {
+            return Err(());
+        }
+        self.page_size = page_size;
+        Ok(())
+    }
+
+    /// The 0-based index of the currently selected element in its page.
+    pub fn cursor_pos_in_page(&self) -> u32 {
+        self.cursor_pos % self.page_size
+    }
+
+    /// Returns the candidate at `index_in_page` in the current page
+    pub fn get_candidate_by_index_in_page(&self, index_in_page: u32) -> Option<Candidate> {
+        if index_in_page >= self.page_size {
+            return None;
+        }
+        let i = (self.cursor_pos - self.cursor_pos_in_page() + index_in_page) as usize;
+        if i < self.source.len() {
+            self.source.candidates(i..i + 1).pop()
+        } else {
+            None
+        }
+    }
+
+    /// 发送的候选项范围 (按页对齐)
+    fn window_range(&self) -> Range<usize> {
+        let p = self.page_size as usize;
+        let w = self.window as usize;
+        let page = self.cursor_pos as usize / p;
+        let start = page.saturating_sub(w) * p;
+        let end = ((page + w + 1) * p).min(self.source.len());
+        start..end.max(start)
+    }
+
+    /// The part of the table sent to ibus (for [`crate::ibus::IBusEngineBackend::update_lookup_table`])
+    ///
+    /// The cursor position is relative to the window. The window only wraps around (`round`)
+    /// if it contains the full list: otherwise it would wrap at the edge of the window, use
+    /// the methods of this table instead.
+    pub fn lookup_table(&self) -> LookupTable {
+        let r = self.window_range();
+        let start = r.start;
+        let round = self.round && r.len() == self.source.len();
+        let mut t = LookupTable::new(vec![], self.page_size, self.cursor_visible, round).unwrap();
+        t.extend(self.source.candidates(r));
+        t.set_cursor_pos(self.cursor_pos as i64 - start as i64);
+        t.labels = self.labels.clone();
+        t.orientation = self.orientation;
+        t.attachments = self.attachments.clone();
+        t
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn virtual_lookup_table_cursor() {
+        let c: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        for round in [false, true] {
+            let mut t = LookupTable::new(c.clone(), 3, true, round).unwrap();
+            let v: Vec<Candidate> = c.iter().cloned().map(Candidate::from).collect();
+            let mut v = VirtualLookupTable::new(v, 3, true, round).unwrap();
+            for op in [0, 0, 1, 1, 1, 1, 2, 3, 3, 1, 0, 0, 0, 0, 0, 2, 2, 2, 2, 3] {
+                match op {
+                    0 => (t.page_up(), v.page_up()),
+                    1 => (t.page_down(), v.page_down()),
+                    2 => (t.cursor_up(), v.cursor_up()),
+                    _ => (t.cursor_down(), v.cursor_down()),
+                };
+                assert_eq!(t.cursor_pos(), v.cursor_pos());
+                for i in 0..3 {
+                    assert_eq!(
+                        t.get_candidate_by_index_in_page(i).cloned(),
+                        v.get_candidate_by_index_in_page(i)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn virtual_lookup_table_window() {
+        let c: Vec<Candidate> = (0..10).map(|i| Candidate::new(i.to_string())).collect();
+        let mut v = VirtualLookupTable::new(c, 3, true, false).unwrap();
+        v.set_cursor_pos(7);
+        let p = v.lookup_table();
+        assert_eq!(p.candidates(), &["6", "7", "8"]);
+        assert_eq!(p.cursor_pos(), 1);
+
+        v.window = 1;
+        let p = v.lookup_table();
+        assert_eq!(p.candidates(), &["3", "4", "5", "6", "7", "8", "9"]);
+        assert_eq!(p.cursor_pos(), 4);
+        assert_eq!(p.cursor_pos_in_page(), v.cursor_pos_in_page());
+
+        let v = VirtualLookupTable::new(Vec::<Candidate>::new(), 3, true, false).unwrap();
+        assert!(v.lookup_table().candidates().is_empty());
+        assert!(VirtualLookupTable::new(Vec::<Candidate>::new(), 17, true, false).is_err());
+    }
+
+    // 循环: 在第一个和最后一个候选项, 而不是在窗口的边缘
+    #[test]
+    fn virtual_lookup_table_round() {
+        let c: Vec<Candidate> = (0..10).map(|i| Candidate::new(i.to_string())).collect();
+        let mut v = VirtualLookupTable::new(c, 3, true, true).unwrap();
+        v.set_cursor_pos(8);
+        let mut p = v.lookup_table();
+        assert_eq!(p.candidates(), &["6", "7", "8"]);
+        assert!(!p.round);
+        // 窗口里不循环
+        p.cursor_down();
+        assert_eq!(p.cursor_pos(), 2);
+
+        v.cursor_down();
+        assert_eq!(v.lookup_table().candidates(), &["9"]);
+        v.cursor_down();
+        assert_eq!(v.cursor_pos(), 0);
+        assert_eq!(v.lookup_table().candidates(), &["0", "1", "2"]);
+        v.page_up();
+        assert_eq!(v.cursor_pos(), 9);
+
+        // 窗口包含全部候选项
+        v.window = 4;
+        assert!(v.lookup_table().round);
+    }
+}
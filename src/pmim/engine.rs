@@ -2,63 +2,150 @@ use xkeysym::{KeyCode, Keysym};
 use zbus::{ObjectServer, fdo, object_server::SignalEmitter};
 
 use super::server::Pmims;
-use crate::ibus::{IBusEngine, IBusFactory, IBusModifierState};
+use crate::ibus::{
+    EngineContext, IBusEngine, IBusEngineBackend, IBusFactory, IBusInputHints, IBusInputPurpose,
+    IBusModifierState, IBusPropList, IBusPropState, IBusPropType, IBusProperty,
+};
+
+/// 输入模式 属性 (`icon_prop_key`)
+///
+/// 参见: [`component`](super::component)
+pub(crate) const PROP_INPUT_MODE: &str = "InputMode";
+
+/// 输入模式 属性: 中文 (胖喵拼音) 或 英文 (不捕捉按键), 点击切换
+fn 输入模式(英文: bool) -> IBusProperty {
+    let label = if 英文 { "英文" } else { "胖喵拼音" };
+    let mut p = IBusProperty::new(PROP_INPUT_MODE, IBusPropType::Normal, label);
+    p.symbol = if 英文 { "英" } else { "喵" }.into();
+    p.tooltip = "输入模式 (点击切换 中文/英文)".into();
+    p
+}
+
+/// 输入法的属性 (显示在 ibus 面板)
+fn 属性列表(英文: bool) -> IBusPropList {
+    let mut l = IBusPropList::new();
+    l.append(输入模式(英文));
+    l
+}
 
 #[derive(Debug, Clone)]
 pub struct PmimEngine {
     s: Pmims,
+    ctx: Option<EngineContext>,
+    /// 当前输入框的用途
+    purpose: IBusInputPurpose,
+    /// 英文模式: 不捕捉按键
+    英文: bool,
 }
 
 impl PmimEngine {
     pub fn new(s: Pmims) -> Self {
-        Self { s }
+        Self {
+            s,
+            ctx: None,
+            purpose: IBusInputPurpose::FreeForm,
+            英文: false,
+        }
+    }
+
+    /// pmim-server 的消息 (提交文本) 发送给这个 engine
+    async fn 当前(&mut self) -> &mut Pmims {
+        if let Some(ctx) = &self.ctx {
+            self.s.set_context(ctx).await;
+        }
+        &mut self.s
     }
 }
 
 impl IBusEngine for PmimEngine {
+    fn set_context(&mut self, ctx: EngineContext) {
+        self.ctx = Some(ctx);
+    }
+
     async fn process_key_event(
         &mut self,
-        se: SignalEmitter<'_>,
-        server: &ObjectServer,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
         keyval: Keysym,
         keycode: KeyCode,
         state: IBusModifierState,
     ) -> fdo::Result<bool> {
-        self.s
-            .process_key_event(se, server, keyval.into(), keycode.into(), state.raw_value())
+        // 密码输入框, 英文模式: 不捕捉按键
+        if self.purpose.is_hidden() || self.英文 {
+            return Ok(false);
+        }
+        self.当前()
+            .await
+            .process_key_event(keyval.into(), keycode.into(), state.raw_value())
             .await
     }
 
     async fn set_cursor_location(
         &mut self,
-        se: SignalEmitter<'_>,
-        server: &ObjectServer,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
         x: i32,
         y: i32,
         w: i32,
         h: i32,
     ) -> fdo::Result<()> {
-        self.s.set_cursor_location(se, server, x, y, w, h).await
+        self.当前().await.set_cursor_location(x, y, w, h).await
+    }
+
+    async fn set_content_type(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        purpose: IBusInputPurpose,
+        _hints: IBusInputHints,
+    ) -> fdo::Result<()> {
+        self.purpose = purpose;
+        Ok(())
+    }
+
+    async fn focus_in(&mut self, se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        // 忽略错误
+        let _ = Self::register_properties(&se, &属性列表(self.英文)).await;
+        self.当前().await.focus_in().await
     }
 
-    async fn focus_in(&mut self, se: SignalEmitter<'_>, server: &ObjectServer) -> fdo::Result<()> {
-        self.s.focus_in(se, server).await
+    async fn property_activate(
+        &mut self,
+        se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+        name: String,
+        _state: IBusPropState,
+    ) -> fdo::Result<()> {
+        if name != PROP_INPUT_MODE {
+            return Ok(());
+        }
+        self.英文 = !self.英文;
+        // 切换到英文: 取消正在输入的文本
+        if self.英文 {
+            self.当前().await.reset().await?;
+        }
+        Self::update_property(&se, &输入模式(self.英文)).await?;
+        Ok(())
     }
 
-    async fn focus_out(&mut self, se: SignalEmitter<'_>, server: &ObjectServer) -> fdo::Result<()> {
-        self.s.focus_out(se, server).await
+    async fn focus_out(
+        &mut self,
+        _se: SignalEmitter<'_>,
+        _server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        self.当前().await.focus_out().await
     }
 
-    async fn reset(&mut self, se: SignalEmitter<'_>, server: &ObjectServer) -> fdo::Result<()> {
-        self.s.reset(se, server).await
+    async fn reset(&mut self, _se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.当前().await.reset().await
     }
 
-    async fn enable(&mut self, se: SignalEmitter<'_>, server: &ObjectServer) -> fdo::Result<()> {
-        self.s.enable(se, server).await
+    async fn enable(&mut self, _se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.当前().await.enable().await
     }
 
-    async fn disable(&mut self, se: SignalEmitter<'_>, server: &ObjectServer) -> fdo::Result<()> {
-        self.s.disable(se, server).await
+    async fn disable(&mut self, _se: SignalEmitter<'_>, _server: &ObjectServer) -> fdo::Result<()> {
+        self.当前().await.disable().await
     }
 }
 
@@ -74,7 +161,7 @@ impl PmimFactory {
 }
 
 impl IBusFactory<PmimEngine> for PmimFactory {
-    fn create_engine(&mut self, name: String) -> Result<PmimEngine, String> {
+    async fn create_engine(&mut self, name: String) -> Result<PmimEngine, String> {
         let 名称 = "pmim";
 
         if 名称 == name {
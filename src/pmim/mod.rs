@@ -2,49 +2,114 @@
 //!
 //! <https://github.com/fm-elpac/pmim>
 use std::error::Error;
-use std::process::Command;
+use std::process::{Command, ExitCode};
 
 use pm_bin::log::{debug, info};
-use tokio::{
-    runtime::Runtime,
-    time::{Duration, sleep},
-};
 
-use crate::ibus::IBus;
-use crate::ibus::get_ibus_addr;
+use crate::ibus::{IBus, IBusComponent, IBusEngineDesc, IBusShutdown};
+use crate::ibus::{block_on, get_ibus_addr};
 
 pub mod engine;
 mod server;
 
 use engine::PmimFactory;
 
-pub fn main(flatpak: bool) -> Result<(), Box<dyn Error>> {
+/// 组件名称 (D-Bus)
+const COMPONENT_NAME: &str = "org.fm_elpac.pmim";
+
+/// 组件信息 (`ibrus --xml`)
+///
+/// 安装在: `/usr/share/ibus/component/pmim_ibrus.xml`
+pub fn component() -> IBusComponent {
+    let mut c = IBusComponent::new(COMPONENT_NAME, "/usr/lib/pmim/ibrus --flatpak");
+    c.description = "PMIM (ibus)".to_string();
+    c.version = env!("CARGO_PKG_VERSION").to_string();
+    c.author = "secext2022".to_string();
+    c.license = "GPL".to_string();
+    c.homepage = "https://github.com/fm-elpac/pmim-ibus".to_string();
+    c.textdomain = "pmim-ibus".to_string();
+
+    let mut e = IBusEngineDesc::new("pmim", "胖喵拼音");
+    e.description = "胖喵拼音输入法 (ibus)".to_string();
+    e.language = "zh_CN".to_string();
+    e.license = c.license.clone();
+    e.author = c.author.clone();
+    e.layout = "default".to_string();
+    e.rank = 99;
+    e.symbol = "喵".to_string();
+    e.icon_prop_key = engine::PROP_INPUT_MODE.to_string();
+    e.textdomain = c.textdomain.clone();
+    c.engines.push(e);
+    c
+}
+
+/// `standalone`: 注册组件 (不是由 ibus-daemon 启动)
+///
+/// 返回: 进程的退出码
+pub fn main(flatpak: bool, standalone: bool) -> Result<ExitCode, Box<dyn Error>> {
     debug!("init");
 
     let 地址 = get_ibus_addr()?;
     info!("ibus addr: {}", 地址);
 
+    let mut 子进程 = None;
     if flatpak {
         // 运行命令: `flatpak run io.github.fm_elpac.pmim_ibus`
         info!("run: flatpak run io.github.fm_elpac.pmim_ibus");
 
-        Command::new("flatpak")
-            .args(["run", "io.github.fm_elpac.pmim_ibus"])
-            .spawn()?;
+        子进程 = Some(
+            Command::new("flatpak")
+                .args(["run", "io.github.fm_elpac.pmim_ibus"])
+                .spawn()?,
+        );
     }
 
-    let rt = Runtime::new()?;
-    rt.block_on(async {
+    let r = block_on(async {
         let s = server::初始化pmims(flatpak).await?;
 
-        let 名称 = "org.fm_elpac.pmim";
-        let _b = IBus::new(地址, PmimFactory::new(s), 名称.to_string()).await?;
-
+        let f = PmimFactory::new(s.clone());
+        let mut b = if standalone {
+            IBus::standalone(地址, f, &component()).await?
+        } else {
+            IBus::new(地址, f, COMPONENT_NAME.to_string()).await?
+        };
         info!("初始化完毕");
 
-        loop {
-            sleep(Duration::from_secs(10)).await;
-        }
-        //Ok(())
-    })
+        let r = loop {
+            match b.run_until_shutdown().await? {
+                // 独立运行: ibus-daemon 重启后重新注册组件
+                // (由 ibus-daemon 启动时, ibus-daemon 会重新启动 ibrus)
+                IBusShutdown::Disconnected | IBusShutdown::AddressChanged(_) if standalone => {
+                    b.reconnect_with_retry(|| PmimFactory::new(s.clone()))
+                        .await?;
+                    info!("重新连接: {}", b.addr());
+                }
+                r => break r,
+            }
+        };
+
+        s.close().await;
+        Ok::<_, Box<dyn Error>>(r)
+    });
+
+    if let Some(mut c) = 子进程 {
+        debug!("结束 flatpak 子进程");
+        // 忽略错误
+        let _ = c.kill();
+        let _ = c.wait();
+    }
+
+    Ok(ExitCode::from(r?.exit_code()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 打包时使用 `ibrus --xml` 生成的文件
+    #[test]
+    fn pmim_component_xml() {
+        let x = component().to_xml();
+        assert_eq!(IBusComponent::from_xml(&x).unwrap(), component());
+    }
 }
@@ -1,19 +1,20 @@
 //! `AtK`: Km 按键管理器 运行的任务
-use tokio::sync::mpsc;
+use async_channel::{Receiver, Sender};
 
 use super::super::m::{MSender, Mk, Ms};
 use super::km::Km;
+use crate::ibus::spawn;
 
-async fn 任务(mut r: mpsc::Receiver<Mk>, s: MSender<Ms>) {
+async fn 任务(r: Receiver<Mk>, s: MSender<Ms>) {
     let mut km = Km::new(s);
 
     loop {
         match r.recv().await {
-            Some(m) => match m {
+            Ok(m) => match m {
                 Mk::ProcessKeyEvent((keyval, keycode, state, ret)) => {
                     let 结果 = km.process_key_event(keyval, keycode, state).await;
                     // 忽略错误
-                    let _ = ret.send(结果);
+                    let _ = ret.try_send(结果);
                 }
                 Mk::FocusIn => {
                     km.focus_in().await;
@@ -34,7 +35,7 @@ async fn 任务(mut r: mpsc::Receiver<Mk>, s: MSender<Ms>) {
                     km.输入反馈(f).await;
                 }
             },
-            None => {
+            Err(_) => {
                 break;
             }
         }
@@ -42,10 +43,10 @@ async fn 任务(mut r: mpsc::Receiver<Mk>, s: MSender<Ms>) {
 }
 
 /// 启动 `AtK` 任务
-pub fn at_k(s: MSender<Ms>) -> mpsc::Sender<Mk> {
-    let (tx, rx) = mpsc::channel::<Mk>(16);
+pub fn at_k(s: MSender<Ms>) -> Sender<Mk> {
+    let (tx, rx) = async_channel::bounded::<Mk>(16);
 
-    tokio::spawn(async move {
+    spawn(async move {
         任务(rx, s).await;
     });
 
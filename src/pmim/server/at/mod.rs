@@ -11,4 +11,4 @@ mod s;
 
 pub use k::at_k;
 pub use r::at_r;
-pub use s::at_s;
+pub use s::{at_s, 关闭};
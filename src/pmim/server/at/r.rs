@@ -1,26 +1,24 @@
 //! `AtR`: 从 pmim-server 接收消息 (中转) 的任务
-use tokio::sync::mpsc;
-use zbus::object_server::SignalEmitter;
+use async_channel::{Receiver, Sender};
 
-use super::super::super::engine::PmimEngine;
 use super::super::m::{Mk, Mr};
-use crate::ibus::IBusEngineBackend;
+use crate::ibus::{EngineContext, spawn};
 
-async fn 任务(mut r: mpsc::Receiver<Mr>) {
+async fn 任务(r: Receiver<Mr>) {
     // 按键管理器 消息发送端
-    let mut k: Option<mpsc::Sender<Mk>> = None;
-    // SignalEmitter
-    let mut se: Option<SignalEmitter<'static>> = None;
+    let mut k: Option<Sender<Mk>> = None;
+    // 当前的 engine
+    let mut ctx: Option<EngineContext> = None;
 
     // 不停的接收消息
     loop {
         match r.recv().await {
-            Some(m) => match m {
+            Ok(m) => match m {
                 // 提交文本 (CommitText)
                 Mr::T(t) => {
-                    if let Some(se) = &se {
+                    if let Some(ctx) = &ctx {
                         // 忽略错误
-                        let _ = PmimEngine::commit_text(se, t.0).await;
+                        let _ = ctx.commit_text(t.0).await;
                     }
                     // 忽略
                 }
@@ -32,16 +30,16 @@ async fn 任务(mut r: mpsc::Receiver<Mr>) {
                     }
                     // 忽略
                 }
-                // 更新 SignalContext
-                Mr::SE(x) => {
-                    se = Some(x);
+                // 更新 EngineContext
+                Mr::C(x) => {
+                    ctx = Some(x);
                 }
                 // 更新 按键管理器 消息发送端
                 Mr::K(x) => {
                     k = Some(x);
                 }
             },
-            None => {
+            Err(_) => {
                 break;
             }
         }
@@ -49,11 +47,11 @@ async fn 任务(mut r: mpsc::Receiver<Mr>) {
 }
 
 /// 启动 `AtR` 任务
-pub fn at_r() -> mpsc::Sender<Mr> {
+pub fn at_r() -> Sender<Mr> {
     // 发送消息的通道
-    let (tx, rx) = mpsc::channel(256);
+    let (tx, rx) = async_channel::bounded(256);
 
-    tokio::spawn(async move {
+    spawn(async move {
         任务(rx).await;
     });
 
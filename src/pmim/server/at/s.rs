@@ -1,15 +1,19 @@
 //! `AtS`: 给 pmim-server 发送消息的任务
+use async_channel::{Receiver, Sender};
+use async_io::{Async, Timer};
+use futures_lite::{
+    AsyncBufReadExt, AsyncWriteExt, StreamExt, future,
+    io::{BufReader, BufWriter, ReadHalf, WriteHalf, split},
+};
 use pm_bin::log::{debug, error, info};
 use std::env;
 use std::error::Error;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
-    net::{UnixStream, unix::OwnedReadHalf},
-    sync::mpsc,
-    time::{Duration, sleep},
-};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
 
 use super::super::m::{MSender, Mr, Ms, MsS};
+use crate::ibus::spawn;
 
 /// 获取 pmim-server unix socket 文件路径
 /// ${XDG_RUNTIME_DIR}/pmim/us
@@ -18,10 +22,11 @@ pub fn pmim_us() -> Result<String, Box<dyn Error>> {
     Ok(format!("{}/pmim/us", xrd))
 }
 
-async fn 接收消息(r: OwnedReadHalf, s: mpsc::Sender<Mr>) -> Result<(), Box<dyn Error>> {
+async fn 接收消息(r: ReadHalf<Async<UnixStream>>, s: Sender<Mr>) -> Result<(), Box<dyn Error>> {
     let mut r = BufReader::new(r).lines();
     // 读行
-    while let Some(l) = r.next_line().await? {
+    while let Some(l) = r.next().await {
+        let l = l?;
         match Mr::from(l.clone()) {
             Some(m) => {
                 // 忽略错误
@@ -36,43 +41,80 @@ async fn 接收消息(r: OwnedReadHalf, s: mpsc::Sender<Mr>) -> Result<(), Box<d
     Ok(())
 }
 
+/// 关闭连接 (退出之前): 完成后通知
+pub type 关闭 = Sender<()>;
+
+/// 等待 `关闭` (通道关闭时一直等待)
+async fn 等待关闭(c: &Receiver<关闭>) -> 关闭 {
+    match c.recv().await {
+        Ok(x) => x,
+        Err(_) => future::pending().await,
+    }
+}
+
+async fn 写入(
+    w: &mut BufWriter<WriteHalf<Async<UnixStream>>>,
+    m: Ms,
+) -> Result<(), Box<dyn Error>> {
+    // 消息字节数据
+    let b = m.to_string();
+    w.write_all(b.as_bytes()).await?;
+    // 写入换行
+    w.write_all(b"\n").await?;
+    // 写入完毕
+    w.flush().await?;
+    Ok(())
+}
+
+/// 收到 `关闭` 时返回 `Some`
 async fn 连接服务单次(
     ps: String,
     s: MSender<Ms>,
-    r: &mut mpsc::Receiver<Ms>,
-    sr: mpsc::Sender<Mr>,
-) -> Result<(), Box<dyn Error>> {
+    r: &Receiver<Ms>,
+    sr: Sender<Mr>,
+    c: &Receiver<关闭>,
+) -> Result<Option<关闭>, Box<dyn Error>> {
     debug!("连接 {}", ps);
 
-    let (rx, tx) = UnixStream::connect(ps).await?.into_split();
+    let u = Async::<UnixStream>::connect(ps).await?;
+    // 用于关闭 (写入端)
+    let u1 = u.get_ref().try_clone()?;
+    let (rx, tx) = split(u);
     // 连接成功
     s.已连接(true);
     // 启动接收任务
-    tokio::spawn(async move {
+    spawn(async move {
         // 忽略错误
         let _ = 接收消息(rx, sr).await;
     });
     // 发送连接成功消息
     let s1 = s.clone();
-    tokio::spawn(async move {
+    spawn(async move {
         let _ = s1.send(Ms::S(MsS("ok".to_string()))).await;
     });
 
     let mut w = BufWriter::new(tx);
     // 不停的发送消息
     loop {
-        match r.recv().await {
-            Some(m) => {
-                // 消息字节数据
-                let b = m.to_string();
-                w.write_all(b.as_bytes()).await?;
-                // 写入换行
-                w.write_u8(b'\n').await?;
-                // 写入完毕
-                w.flush().await?;
+        let m = future::or(async { Ok(r.recv().await.ok()) }, async {
+            Err(等待关闭(c).await)
+        })
+        .await;
+        match m {
+            Ok(Some(m)) => {
+                写入(&mut w, m).await?;
             }
-            None => {
-                return Ok(());
+            Ok(None) => {
+                return Ok(None);
+            }
+            Err(x) => {
+                // 发送剩余的消息, 然后关闭
+                while let Ok(m) = r.try_recv() {
+                    写入(&mut w, m).await?;
+                }
+                w.close().await?;
+                u1.shutdown(Shutdown::Write)?;
+                return Ok(Some(x));
             }
         }
     }
@@ -81,14 +123,24 @@ async fn 连接服务单次(
 async fn 任务(
     ps: String,
     s: MSender<Ms>,
-    mut r: mpsc::Receiver<Ms>,
-    sr: mpsc::Sender<Mr>,
+    r: Receiver<Ms>,
+    sr: Sender<Mr>,
+    c: Receiver<关闭>,
     flatpak: bool,
 ) {
     loop {
-        let _ = 连接服务单次(ps.clone(), s.clone(), &mut r, sr.clone()).await;
+        // 忽略错误
+        let x = 连接服务单次(ps.clone(), s.clone(), &r, sr.clone(), &c)
+            .await
+            .ok()
+            .flatten();
         // 连接断开
         s.已连接(false);
+        if let Some(x) = x {
+            debug!("关闭连接");
+            let _ = x.send(()).await;
+            return;
+        }
 
         // 重新连接之前等待的时间 (秒)
         let mut w = 2;
@@ -96,23 +148,88 @@ async fn 任务(
             w = 1;
         }
         debug!("连接断开, {}s 后重试 .. .", w);
-        sleep(Duration::from_secs(w)).await;
+        let x = future::or(
+            async {
+                Timer::after(Duration::from_secs(w)).await;
+                None
+            },
+            async { Some(等待关闭(&c).await) },
+        )
+        .await;
+        if let Some(x) = x {
+            let _ = x.send(()).await;
+            return;
+        }
     }
 }
 
-/// 启动 `AtS` 任务
-pub fn at_s(sr: mpsc::Sender<Mr>, flatpak: bool) -> Result<MSender<Ms>, Box<dyn Error>> {
-    let ps = pmim_us()?;
-    info!("{}", ps);
-
+/// 启动 `AtS` 任务, 连接 `ps`
+fn 启动(ps: String, sr: Sender<Mr>, flatpak: bool) -> (MSender<Ms>, Sender<关闭>) {
     // 发送消息的通道
-    let (tx, rx) = mpsc::channel(256);
+    let (tx, rx) = async_channel::bounded(256);
     let s = MSender::<Ms>::new(tx);
+    let (ctx, crx) = async_channel::bounded(1);
 
     let s1 = s.clone();
-    tokio::spawn(async move {
-        任务(ps, s1, rx, sr, flatpak).await;
+    spawn(async move {
+        任务(ps, s1, rx, sr, crx, flatpak).await;
     });
 
-    Ok(s)
+    (s, ctx)
+}
+
+/// 启动 `AtS` 任务
+///
+/// 返回: (发送消息, 关闭连接)
+pub fn at_s(
+    sr: Sender<Mr>,
+    flatpak: bool,
+) -> Result<(MSender<Ms>, Sender<关闭>), Box<dyn Error>> {
+    let ps = pmim_us()?;
+    info!("{}", ps);
+
+    Ok(启动(ps, sr, flatpak))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ibus::block_on;
+    use std::os::unix::net::UnixListener;
+
+    // 模拟 pmim-server
+    #[test]
+    fn at_s_pmim_server() {
+        let ps = env::temp_dir().join(format!("librush-at-s-{}", std::process::id()));
+        let _ = std::fs::remove_file(&ps);
+        let l = Async::<UnixListener>::bind(&ps).unwrap();
+
+        block_on(async {
+            let (sr, mr) = async_channel::bounded(4);
+            let (s, c) = 启动(ps.to_string_lossy().to_string(), sr, false);
+
+            let (u, _) = l.accept().await.unwrap();
+            let (rx, mut tx) = split(u);
+            let mut rx = BufReader::new(rx).lines();
+            assert_eq!(rx.next().await.unwrap().unwrap(), r#"S "ok""#);
+
+            s.send(Ms::S(MsS("focus_in".to_string()))).await.unwrap();
+            assert_eq!(rx.next().await.unwrap().unwrap(), r#"S "focus_in""#);
+
+            tx.write_all("t \"喵\"\n".as_bytes()).await.unwrap();
+            match mr.recv().await.unwrap() {
+                Mr::T(t) => assert_eq!(t.0, "喵"),
+                m => panic!("{:?}", m),
+            }
+
+            // 关闭: 发送剩余的消息
+            s.send(Ms::S(MsS("disable".to_string()))).await.unwrap();
+            let (x, y) = async_channel::bounded(1);
+            c.send(x).await.unwrap();
+            y.recv().await.unwrap();
+            assert_eq!(rx.next().await.unwrap().unwrap(), r#"S "disable""#);
+            assert!(rx.next().await.is_none());
+        });
+        let _ = std::fs::remove_file(&ps);
+    }
 }
@@ -1,4 +1,4 @@
-use tokio::sync::oneshot::Sender;
+use async_channel::Sender;
 
 /// 消息: `Km` 按键管理器 <- IBusEngine
 #[derive(Debug)]
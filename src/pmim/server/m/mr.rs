@@ -1,19 +1,19 @@
-use tokio::sync::mpsc;
-use zbus::object_server::SignalEmitter;
+use async_channel::Sender;
 
 use super::Mk;
+use crate::ibus::EngineContext;
 
-/// 消息: ibrus (SignalEmitter) <- pmim-server
+/// 消息: ibrus (EngineContext) <- pmim-server
 #[derive(Debug, Clone)]
 pub enum Mr {
     /// `t`: 提交文本 (CommitText)
     T(MrT),
     /// `f`: 输入反馈
     F(MrF),
-    /// SignalEmitter
-    SE(SignalEmitter<'static>),
+    /// 当前的 engine
+    C(EngineContext),
     /// 按键管理器 消息发送端
-    K(mpsc::Sender<Mk>),
+    K(Sender<Mk>),
 }
 
 const MR_T: &'static str = "t";
@@ -1,8 +1,8 @@
+use async_channel::{SendError, Sender};
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
-use tokio::sync::mpsc::{Sender, error::SendError};
 
 /// 根据连接状态发送消息
 #[derive(Debug, Clone)]